@@ -1,72 +1,338 @@
-use crate::canvas::Canvas;
-use crate::state::State;
+use crate::buffer::BufferPool;
+use crate::canvas::{Canvas, Frame, ModuleSlot};
+use crate::layout::Insets;
+use crate::state::{BarId, State};
 
-use std::fs::File;
+use std::rc::Rc;
+
+use serde::Deserialize;
 
 use wayland_client::{
     protocol::*,
+    Proxy,
 };
 
+use wayland_protocols::wp::fractional_scale::v1::client::*;
+use wayland_protocols::wp::viewporter::client::*;
 use wayland_protocols_wlr::layer_shell::v1::client::*;
 
+/// The output edge a bar is anchored to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BarPosition {
     Top,
     Bottom,
+    Left,
+    Right,
+}
+
+impl BarPosition {
+    pub fn is_vertical(&self) -> bool {
+        matches!(self, BarPosition::Left | BarPosition::Right)
+    }
+}
+
+/// The layer-shell layer a bar lives in, from below normal windows to above fullscreen ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BarLayer {
+    Background,
+    Bottom,
+    #[default]
+    Top,
+    Overlay,
+}
+
+/// How much of the output a bar reserves for itself, so that windows are not placed under it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExclusiveZone {
+    /// The size of the bar, the compositor adds the margin on the anchored edge
+    #[default]
+    Auto,
+    /// Nothing, the bar floats above windows
+    None,
+    /// The given distance from the anchored edge
+    Custom(u32),
+}
+
+/// Whether a bar takes keyboard focus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyboardInteractivity {
+    #[default]
+    None,
+    /// Grabs the keyboard for as long as the bar is mapped, only honored in the top and overlay layers
+    Exclusive,
+    /// Focused when clicked, like a normal window. Needs layer-shell version 4, `None` before that
+    OnDemand,
+}
+
+/// How a bar is placed on its outputs.
+///
+/// ```
+/// # use ruwabar::{BarConfig, BarLayer, BarPosition, ExclusiveZone, Insets};
+/// let config = BarConfig::new(BarPosition::Top, 30)
+///     .layer(BarLayer::Overlay)
+///     .margin(Insets::all(8))
+///     .exclusive_zone(ExclusiveZone::None);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BarConfig {
+    pub(crate) position: BarPosition,
+    /// Height of horizontal bars, width of vertical ones
+    pub(crate) size: u32,
+    pub(crate) output: BarOutput,
+    pub(crate) layer: BarLayer,
+    pub(crate) margin: Insets,
+    pub(crate) exclusive_zone: ExclusiveZone,
+    pub(crate) namespace: String,
+    pub(crate) keyboard_interactivity: KeyboardInteractivity,
+}
+
+/// Layer-shell does not allow zero-sized surfaces, a bar sized 0 would be a protocol error.
+pub(crate) const MIN_BAR_SIZE: u32 = 1;
+
+impl BarConfig {
+    /// `size` is clamped to at least a pixel.
+    pub fn new(position: BarPosition, size: u32) -> Self {
+        Self {
+            position,
+            size: size.max(MIN_BAR_SIZE),
+            output: BarOutput::All,
+            layer: BarLayer::default(),
+            margin: Insets::default(),
+            exclusive_zone: ExclusiveZone::default(),
+            namespace: "ruwabar".to_string(),
+            keyboard_interactivity: KeyboardInteractivity::default(),
+        }
+    }
+
+    pub fn output(mut self, output: BarOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn layer(mut self, layer: BarLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Distance to the output edges, margins on edges the bar is not anchored to are ignored.
+    pub fn margin(mut self, margin: Insets) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn exclusive_zone(mut self, exclusive_zone: ExclusiveZone) -> Self {
+        self.exclusive_zone = exclusive_zone;
+        self
+    }
+
+    /// Lets the compositor tell bars apart, e.g. in window rules.
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    pub fn keyboard_interactivity(mut self, keyboard_interactivity: KeyboardInteractivity) -> Self {
+        self.keyboard_interactivity = keyboard_interactivity;
+        self
+    }
+}
+
+/// Which outputs a bar is shown on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BarOutput {
+    /// One bar per connected output, following hot-plugs.
+    All,
+    /// A single bar on the output the compositor considers focused.
+    Focused,
+    /// Bars on every output with the given name, e.g. `"DP-1"`.
+    Name(String),
+}
+
+pub type DrawFn = Rc<dyn Fn(&mut Canvas)>;
+
+/// A bar as requested by the user, instantiated once per matching output.
+pub(crate) struct BarSpec {
+    pub(crate) config: BarConfig,
+    pub(crate) draw: DrawFn,
+    /// Hidden bars have no surfaces, so they do not reserve space either
+    pub(crate) hidden: bool,
 }
 
 pub struct Bar {
+    pub(crate) id: BarId,
+    pub(crate) spec: usize,
+    pub(crate) output: Option<u32>,
+
+    /// Logical size of the surface
+    pub(crate) width: u32,
     pub(crate) height: u32,
-    pub(crate) draw: Box<dyn Fn(&mut Canvas) -> ()>,
+    /// Device pixels per logical pixel the buffers are drawn at
+    pub(crate) scale: f32,
+    pub(crate) draw: DrawFn,
+    /// Modules drawn in the last frame, these decide when the bar has to be redrawn
+    pub(crate) slots: Vec<ModuleSlot>,
 
     pub(crate) base_surface: wl_surface::WlSurface,
+    pub(crate) layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    /// Both present when the compositor supports fractional scaling
+    pub(crate) fractional_scale: Option<wp_fractional_scale_v1::WpFractionalScaleV1>,
+    pub(crate) viewport: Option<wp_viewport::WpViewport>,
 
-    pub(crate) tmpfile: Option<File>,
-    pub(crate) canvas: Option<Canvas>,
-    pub(crate) shm_pool: Option<wl_shm_pool::WlShmPool>,
-    pub(crate) buffer: Option<wl_buffer::WlBuffer>,
+    pub(crate) frame: Frame,
+    pub(crate) pool: Option<BufferPool>,
 }
 
 impl Bar {
-    pub fn new<F>(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: BarId,
         compositor: &wl_compositor::WlCompositor,
         layer_shell: &zwlr_layer_shell_v1::ZwlrLayerShellV1,
-        position: BarPosition,
-        height: u32,
-        draw: F,
+        spec: usize,
+        bar_spec: &BarSpec,
+        output: Option<(u32, &wl_output::WlOutput)>,
+        (width, height): (u32, u32),
+        scaling: Option<(&wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, &wp_viewporter::WpViewporter)>,
         qh: &wayland_client::QueueHandle<State>,
-    ) -> Self where F: Fn(&mut Canvas) -> () + 'static {
-        let base_surface = compositor.create_surface(qh, ());
+    ) -> Self {
+        let config = &bar_spec.config;
+
+        let base_surface = compositor.create_surface(qh, id);
         let layer_surface = layer_shell.get_layer_surface(
             &base_surface,
-            None, // Default output
-            zwlr_layer_shell_v1::Layer::Top,
-            "ruwabar".to_string(),
+            output.map(|(_, wl_output)| wl_output), // None means the focused output
+            match config.layer {
+                BarLayer::Background => zwlr_layer_shell_v1::Layer::Background,
+                BarLayer::Bottom => zwlr_layer_shell_v1::Layer::Bottom,
+                BarLayer::Top => zwlr_layer_shell_v1::Layer::Top,
+                BarLayer::Overlay => zwlr_layer_shell_v1::Layer::Overlay,
+            },
+            config.namespace.clone(),
             qh,
-            (),
+            id,
         );
 
-        layer_surface.set_size(0, height);
-        layer_surface.set_anchor(
-            match position {
-                BarPosition::Top => zwlr_layer_surface_v1::Anchor::Top,
-                BarPosition::Bottom => zwlr_layer_surface_v1::Anchor::Bottom,
-            } 
-                | zwlr_layer_surface_v1::Anchor::Left
-                | zwlr_layer_surface_v1::Anchor::Right,
-        );
-        layer_surface.set_exclusive_zone(height as i32);
+        use zwlr_layer_surface_v1::Anchor;
+
+        // Stretched along the anchored edge, the compositor tells the length in the configure
+        let (anchor, width, height) = match config.position {
+            BarPosition::Top => (Anchor::Top | Anchor::Left | Anchor::Right, width, config.size),
+            BarPosition::Bottom => (Anchor::Bottom | Anchor::Left | Anchor::Right, width, config.size),
+            BarPosition::Left => (Anchor::Left | Anchor::Top | Anchor::Bottom, config.size, height),
+            BarPosition::Right => (Anchor::Right | Anchor::Top | Anchor::Bottom, config.size, height),
+        };
+
+        if config.position.is_vertical() {
+            layer_surface.set_size(width, 0);
+        } else {
+            layer_surface.set_size(0, height);
+        }
+        layer_surface.set_anchor(anchor);
+
+        let margin = config.margin;
+        layer_surface.set_margin(margin.top as i32, margin.right as i32, margin.bottom as i32, margin.left as i32);
+
+        layer_surface.set_exclusive_zone(match config.exclusive_zone {
+            ExclusiveZone::Auto => config.size as i32,
+            ExclusiveZone::None => 0,
+            ExclusiveZone::Custom(zone) => zone as i32,
+        });
+
+        layer_surface.set_keyboard_interactivity(match config.keyboard_interactivity {
+            KeyboardInteractivity::Exclusive => zwlr_layer_surface_v1::KeyboardInteractivity::Exclusive,
+            KeyboardInteractivity::OnDemand if layer_shell.version() >= 4 => {
+                zwlr_layer_surface_v1::KeyboardInteractivity::OnDemand
+            }
+            _ => zwlr_layer_surface_v1::KeyboardInteractivity::None,
+        });
+
+        let fractional_scale = scaling.map(|(manager, _)| manager.get_fractional_scale(&base_surface, qh, id));
+        let viewport = scaling.map(|(_, viewporter)| viewporter.get_viewport(&base_surface, qh, ()));
+
         base_surface.commit();
 
         Self {
+            id,
+            spec,
+            output: output.map(|(global_name, _)| global_name),
+
+            width,
             height,
-            draw: Box::new(draw),
+            scale: 1.0,
+            draw: bar_spec.draw.clone(),
+            slots: Vec::new(),
 
             base_surface,
-            
-            tmpfile: None,
-            canvas: None,
-            buffer: None,
-            shm_pool: None,
+            layer_surface,
+            fractional_scale,
+            viewport,
+
+            frame: Frame::default(),
+            pool: None,
+        }
+    }
+
+    pub(crate) fn destroy(&mut self) {
+        self.pool = None;
+
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+
+        self.layer_surface.destroy();
+        self.base_surface.destroy();
+    }
+
+    /// The topmost module drawn at surface-local `x`, `y` in the last frame.
+    pub(crate) fn module_at(&self, x: f64, y: f64) -> Option<&ModuleSlot> {
+        self.slots.iter().rev().find(|slot| slot.rect.contains(x, y))
+    }
+
+    /// Adopts the size assigned by the compositor and the scale to draw at,
+    /// dropping the buffers allocated for the old ones. Takes effect on the next commit.
+    pub(crate) fn resize(&mut self, width: u32, height: u32, scale: f32) {
+        if width == self.width && height == self.height && scale == self.scale && self.pool.is_some() {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.scale = scale;
+
+        match &self.viewport {
+            // The buffer is scaled down to the surface size, whatever its own size
+            Some(viewport) => viewport.set_destination(width as i32, height as i32),
+            // set_buffer_scale needs wl_surface version 3, the scale stays at 1 before that
+            None if self.base_surface.version() >= 3 => self.base_surface.set_buffer_scale(scale as i32),
+            None => {}
         }
+
+        self.pool = None;
     }
-}
\ No newline at end of file
+
+    /// Size of the buffers in device pixels.
+    pub(crate) fn buffer_size(&self) -> (u32, u32) {
+        (
+            (self.width as f32 * self.scale).round() as u32,
+            (self.height as f32 * self.scale).round() as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_size_is_clamped() {
+        assert_eq!(BarConfig::new(BarPosition::Top, 0).size, MIN_BAR_SIZE);
+        assert_eq!(BarConfig::new(BarPosition::Left, 30).size, 30);
+    }
+}
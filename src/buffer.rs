@@ -0,0 +1,195 @@
+use crate::canvas::Rect;
+use crate::damage;
+use crate::error::{Error, Result};
+use crate::state::State;
+
+use std::os::unix::io::AsFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use memfd::{FileSeal, MemfdOptions};
+use memmap2::MmapMut;
+
+use wayland_client::{
+    protocol::*,
+    Proxy,
+};
+
+/// Set while the compositor may still read from the buffer, cleared by `wl_buffer.release`.
+pub(crate) type BufferBusy = Arc<AtomicBool>;
+
+const BUFFER_COUNT: usize = 2;
+
+struct Buffer {
+    wl_buffer: wl_buffer::WlBuffer,
+    offset: usize, // in bytes
+    busy: BufferBusy,
+
+    /// Regions that changed in frames drawn into other buffers, `None` if the content is unknown
+    stale: Option<Vec<Rect>>,
+}
+
+/// A memfd-backed `wl_shm_pool` split into equally sized ARGB8888 buffers.
+pub(crate) struct BufferPool {
+    height: u32,
+    stride: u32, // in bytes
+
+    mmap: MmapMut,
+    shm_pool: wl_shm_pool::WlShmPool,
+    buffers: Vec<Buffer>,
+
+    /// The buffer attached last, which holds what the compositor shows
+    front: Option<usize>,
+}
+
+impl BufferPool {
+    pub(crate) fn new(
+        shm: &wl_shm::WlShm,
+        width: u32,
+        height: u32,
+        qh: &wayland_client::QueueHandle<State>,
+    ) -> Result<Self> {
+        let stride = width * 4;
+        let buffer_size = (stride * height) as usize;
+        let size = buffer_size * BUFFER_COUNT;
+
+        let memfd = MemfdOptions::default()
+            .allow_sealing(true)
+            .create("ruwabar")
+            .map_err(|error| Error::Shm(std::io::Error::other(error)))?;
+        memfd.as_file().set_len(size as u64).map_err(Error::Shm)?;
+        memfd
+            .add_seals(&[FileSeal::SealShrink, FileSeal::SealSeal])
+            .map_err(|error| Error::Shm(std::io::Error::other(error)))?;
+
+        let mmap = unsafe { MmapMut::map_mut(memfd.as_file()) }.map_err(Error::Shm)?;
+        let shm_pool = shm.create_pool(memfd.as_file().as_fd(), size as i32, qh, ());
+
+        let buffers = (0..BUFFER_COUNT)
+            .map(|index| {
+                let offset = index * buffer_size;
+                let busy = BufferBusy::default();
+                let wl_buffer = shm_pool.create_buffer(
+                    offset as i32,
+                    width as i32,
+                    height as i32,
+                    stride as i32,
+                    wl_shm::Format::Argb8888,
+                    qh,
+                    busy.clone(),
+                );
+
+                Buffer { wl_buffer, offset, busy, stale: None }
+            })
+            .collect();
+
+        Ok(Self {
+            height,
+            stride,
+
+            mmap,
+            shm_pool,
+            buffers,
+
+            front: None,
+        })
+    }
+
+    /// Whether nothing has been attached yet, so the whole surface has to be damaged.
+    pub(crate) fn is_fresh(&self) -> bool {
+        self.front.is_none()
+    }
+
+    /// Index of a buffer the compositor is done with, if there is one. Buffers other than the front
+    /// one come first, so that the front buffer is left to compare the new frame with.
+    pub(crate) fn acquire(&self) -> Option<usize> {
+        (0..self.buffers.len())
+            .filter(|&index| !self.buffers[index].busy.load(Ordering::Acquire))
+            .min_by_key(|&index| Some(index) == self.front)
+    }
+
+    /// Brings the buffer up to date with the front buffer by copying the regions it missed.
+    pub(crate) fn prepare(&mut self, index: usize) {
+        let Some(front) = self.front.filter(|&front| front != index) else {
+            return;
+        };
+
+        let stride = self.stride as usize;
+        let src = self.buffers[front].offset;
+        let dst = self.buffers[index].offset;
+
+        let full = Rect::new(0, 0, self.stride / 4, self.height);
+        let stale = self.buffers[index].stale.take().unwrap_or_else(|| vec![full]);
+
+        for rect in stale {
+            let start = rect.x as usize * 4;
+            let end = start + rect.width as usize * 4;
+
+            for row in rect.y as usize..rect.y as usize + rect.height as usize {
+                let line = row * stride;
+                self.mmap.copy_within(src + line + start..src + line + end, dst + line + start);
+            }
+        }
+
+        self.buffers[index].stale = Some(Vec::new());
+    }
+
+    /// The pixels of the front buffer, unless it is the buffer at `index` itself.
+    pub(crate) fn front_pixels(&self, index: usize) -> Option<&[u32]> {
+        self.front.filter(|&front| front != index).map(|front| self.pixels(front))
+    }
+
+    pub(crate) fn pixels(&self, index: usize) -> &[u32] {
+        let offset = self.buffers[index].offset;
+        let size = (self.stride * self.height) as usize;
+        bytemuck::cast_slice(&self.mmap[offset..offset + size])
+    }
+
+    pub(crate) fn pixels_mut(&mut self, index: usize) -> &mut [u32] {
+        let offset = self.buffers[index].offset;
+        let size = (self.stride * self.height) as usize;
+        bytemuck::cast_slice_mut(&mut self.mmap[offset..offset + size])
+    }
+
+    /// Attaches the buffer to `surface` and damages `damage`, the buffer stays busy until the compositor releases it.
+    pub(crate) fn attach(&mut self, index: usize, surface: &wl_surface::WlSurface, damage: &[Rect]) {
+        let buffer = &mut self.buffers[index];
+        buffer.busy.store(true, Ordering::Release);
+        buffer.stale = Some(Vec::new());
+
+        surface.attach(Some(&buffer.wl_buffer), 0, 0);
+        if surface.version() >= 4 {
+            for rect in damage {
+                surface.damage_buffer(rect.x, rect.y, rect.width as i32, rect.height as i32);
+            }
+        } else if !damage.is_empty() {
+            // Older surfaces only take damage in surface coordinates, which a buffer scale or viewport
+            // makes differ from buffer pixels, so damage everything rather than convert
+            surface.damage(0, 0, i32::MAX, i32::MAX);
+        }
+
+        for (other, buffer) in self.buffers.iter_mut().enumerate() {
+            if other == index {
+                continue;
+            }
+
+            if let Some(stale) = &mut buffer.stale {
+                for rect in damage {
+                    damage::add_rect(stale, *rect);
+                }
+            }
+        }
+
+        self.front = Some(index);
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        for buffer in &self.buffers {
+            buffer.wl_buffer.destroy();
+        }
+
+        self.shm_pool.destroy();
+    }
+}
@@ -1,268 +1,708 @@
-use std::sync::Arc;
-use std::sync::Mutex;
-
 use fontdue::Font;
 
+use crate::damage::{Damage, TILE_SIZE};
+use crate::layout::{self, Orientation};
 use crate::modules::*;
+use crate::path::{self, FillRule, LineCap, LineJoin, Path};
+
+/// Device coordinates are clamped to this far off the canvas, which is never drawn
+/// but keeps the pixel arithmetic from overflowing.
+const MAX_COORD: f32 = (1 << 24) as f32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64
+            && y >= self.y as f64
+            && x < self.x as f64 + self.width as f64
+            && y < self.y as f64 + self.height as f64
+    }
+
+    /// The same rectangle with x and y swapped, to go between horizontal and vertical bars.
+    pub(crate) fn transposed(&self) -> Rect {
+        Rect::new(self.y, self.x, self.height, self.width)
+    }
+
+    /// The area covered by both `self` and `other`, empty if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
 
-#[derive(Debug)]
-pub struct Canvas {
-    width: u32,
+        Rect::new(x, y, (right - x as i64).max(0) as u32, (bottom - y as i64).max(0) as u32)
+    }
+
+    /// The smallest rectangle containing both `self` and `other`.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+
+        Rect::new(x, y, (right - x as i64).min(u32::MAX as i64) as u32, (bottom - y as i64).min(u32::MAX as i64) as u32)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // Edges are wider than the fields, so that they cannot overflow
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+}
+
+/// Where a module was drawn, in coordinates of the root canvas.
+#[derive(Clone)]
+pub struct ModuleSlot {
+    pub(crate) module: ModuleHandle,
+    /// The module including its padding
+    pub rect: Rect,
+}
+
+impl ModuleSlot {
+    /// The module drawn here, to compare with `Rc::ptr_eq` against handles from `Modules::add_shared`.
+    pub fn module(&self) -> &ModuleHandle {
+        &self.module
+    }
+}
+
+/// How drawn colors combine with the pixels already on the canvas, following Porter-Duff compositing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Replaces the pixels, alpha included, e.g. to punch transparent holes into a bar
+    Source,
+    /// Paints over the pixels, which show through where the color is translucent
+    #[default]
+    SourceOver,
+    /// Darkens the pixels by multiplying them with the color, white leaving them as they are
+    Multiply,
+    /// Makes the pixels transparent, whatever the color
+    Clear,
+}
+
+/// Bookkeeping of a single frame, shared by a canvas and all of its subcanvases.
+#[derive(Default)]
+pub struct Frame {
+    /// Every module drawn during the frame
+    pub(crate) slots: Vec<ModuleSlot>,
+    /// Pixels that actually changed value during the frame
+    pub(crate) damage: Damage,
+}
+
+/// A view into premultiplied ARGB8888 pixels, typically the mapped memory of a `wl_shm` buffer.
+/// Colors are given as straight, not premultiplied, ARGB and combined with the pixels by the blend mode.
+///
+/// Public drawing methods take logical coordinates and sizes, which are multiplied by
+/// the canvas's scale to get device pixels; the `*_px` helpers work in device pixels.
+/// Coordinates may be fractional or negative, shapes off the canvas are simply cut.
+///
+/// Nothing is drawn outside the clip rectangle, which starts out as the whole canvas, or for a
+/// subcanvas as the part of it inside its parent's clip. `push_clip` narrows it, `pop_clip` restores it.
+///
+/// Lines, ovals, arcs, rounded rectangles and paths are anti-aliased, their edges blended by how much of each pixel they cover.
+pub struct Canvas<'a> {
+    x: u32, // origin in the root canvas, in device pixels
+    y: u32,
+    width: u32, // in device pixels
     height: u32,
-    offset: u32, // in pixels, not bytes
     stride: u32, // in pixels, not bytes
 
-    pub(crate) pixels: Arc<Mutex<Vec<u32>>>,
+    scale: f32,
+    orientation: Orientation,
+    logical_x: i32, // origin in the root canvas, in logical pixels
+    logical_y: i32,
+
+    clip: Rect, // in device pixels relative to the origin, never reaching outside the canvas
+    saved_clips: Vec<Rect>,
+    stroke_width: f32, // in logical pixels
+    line_cap: LineCap,
+    line_join: LineJoin,
+    dashes: Vec<f32>, // in logical pixels
+    dash_offset: f32,
+    blend_mode: BlendMode,
 
-    background_color: u32,
+    pixels: &'a mut [u32],
+    frame: &'a mut Frame,
 }
 
-#[allow(dead_code)]
-impl Canvas {
-    pub fn new(width: u32, height: u32, background_color: u32) -> Self {
+impl<'a> Canvas<'a> {
+    /// Starts a frame drawing into `pixels`, which are expected to hold the previous frame.
+    /// `width` and `height` are in device pixels, `scale` is the number of device pixels per logical one.
+    /// `orientation` is the direction modules are laid out in.
+    pub(crate) fn new(
+        pixels: &'a mut [u32],
+        width: u32,
+        height: u32,
+        scale: f32,
+        orientation: Orientation,
+        frame: &'a mut Frame,
+    ) -> Self {
+        assert!(pixels.len() >= (width * height) as usize, "pixel buffer is smaller than {width}x{height}");
+
+        frame.slots.clear();
+        frame.damage.reset(width, height);
+
         Self {
+            x: 0,
+            y: 0,
             width,
             height,
-            offset: 0,
             stride: width,
-            pixels: Arc::new(Mutex::new(vec![background_color; (width * height) as usize])),
-            background_color,
+            scale,
+            orientation,
+            logical_x: 0,
+            logical_y: 0,
+            clip: Rect::new(0, 0, width, height),
+            saved_clips: Vec::new(),
+            stroke_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+            blend_mode: BlendMode::SourceOver,
+            pixels,
+            frame,
         }
     }
 
-    fn subcanvas(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
-        Self {
+    /// A view of a region of this canvas given in logical pixels, borrowing it for as long as the view lives.
+    /// The region is cut to the canvas.
+    fn subcanvas(&mut self, x: u32, y: u32, width: u32, height: u32) -> Canvas<'_> {
+        let rect = self.to_device(x as f32, y as f32, width as f32, height as f32);
+        let device_x = rect.x.clamp(0, self.width as i32) as u32;
+        let device_y = rect.y.clamp(0, self.height as i32) as u32;
+        let width = rect.width.min(self.width - device_x);
+        let height = rect.height.min(self.height - device_y);
+
+        // The parent's clip, moved to the subcanvas's origin
+        let parent_clip = Rect::new(
+            self.clip.x - device_x as i32,
+            self.clip.y - device_y as i32,
+            self.clip.width,
+            self.clip.height,
+        );
+
+        Canvas {
+            x: self.x + device_x,
+            y: self.y + device_y,
             width,
             height,
-            offset: x + y * self.stride + self.offset,
             stride: self.stride,
-            pixels: self.pixels.clone(),
-            background_color: self.background_color,
+            scale: self.scale,
+            orientation: self.orientation,
+            logical_x: self.logical_x + x as i32,
+            logical_y: self.logical_y + y as i32,
+            clip: parent_clip.intersection(&Rect::new(0, 0, width, height)),
+            saved_clips: Vec::new(),
+            stroke_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+            blend_mode: BlendMode::SourceOver,
+            pixels: self.pixels,
+            frame: self.frame,
         }
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: u32) {
-        if x < self.width && y < self.height {
-            let mut pixels = self.pixels.lock().unwrap();
-            pixels[(x + y * self.stride + self.offset) as usize] = color;
+    /// Restricts drawing to the part of the current clip inside the given logical rectangle, until `pop_clip`.
+    pub fn push_clip(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let rect = self.to_device(x, y, width, height);
+
+        self.saved_clips.push(self.clip);
+        self.clip = self.clip.intersection(&rect);
+    }
+
+    /// Restores the clip from before the last `push_clip`. Does nothing if there is none.
+    pub fn pop_clip(&mut self) {
+        if let Some(clip) = self.saved_clips.pop() {
+            self.clip = clip;
         }
     }
 
-    pub fn draw_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: u32) {
-        let x_end = x + width - 1;
-        let y_end = y + height - 1;
+    /// Width of the lines drawn by the `draw_*` shape methods, in logical pixels.
+    /// Canvases start out with 1, including those of modules.
+    pub fn set_stroke_width(&mut self, width: f32) {
+        self.stroke_width = width.max(0.0);
+    }
 
-        for px in x..=x_end {
-            self.set_pixel(px, y, color);
-            self.set_pixel(px, y_end, color);
-        }
+    pub fn stroke_width(&self) -> f32 {
+        self.stroke_width
+    }
 
-        for py in y..=y_end {
-            self.set_pixel(x, py, color);
-            self.set_pixel(x_end, py, color);
-        }
+    /// How `draw_path` ends open subpaths and dashes. Canvases start out with `Butt`.
+    pub fn set_line_cap(&mut self, cap: LineCap) {
+        self.line_cap = cap;
     }
 
-    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: u32) {
-        for j in y..y + height {
-            for i in x..x + width {
-                self.set_pixel(i, j, color);
-            }
+    pub fn line_cap(&self) -> LineCap {
+        self.line_cap
+    }
+
+    /// How `draw_path` goes around corners. Canvases start out with `Miter`.
+    pub fn set_line_join(&mut self, join: LineJoin) {
+        self.line_join = join;
+    }
+
+    pub fn line_join(&self) -> LineJoin {
+        self.line_join
+    }
+
+    /// Makes `draw_path` alternate between dashes and gaps of the logical lengths in `pattern`, starting
+    /// `offset` into it. An empty pattern, which canvases start out with, draws solid lines.
+    pub fn set_dashes(&mut self, pattern: &[f32], offset: f32) {
+        self.dashes.clear();
+        self.dashes.extend_from_slice(pattern);
+        self.dash_offset = offset;
+    }
+
+    /// How everything drawn from now on combines with the canvas. Canvases start out with `SourceOver`.
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Whether the device pixel at `x`, `y` is inside the clip, and thus the canvas.
+    fn clip_contains(&self, x: i32, y: i32) -> bool {
+        x >= self.clip.x
+            && y >= self.clip.y
+            && (x as i64) < self.clip.right()
+            && (y as i64) < self.clip.bottom()
+    }
+
+    /// Converts a logical coordinate to device pixels.
+    fn px(&self, value: f32) -> i32 {
+        device(value * self.scale)
+    }
+
+    /// Converts a logical rectangle to device pixels, negative sizes making it empty.
+    /// Edges are rounded rather than sizes so that adjacent rectangles stay adjacent.
+    fn to_device(&self, x: f32, y: f32, width: f32, height: f32) -> Rect {
+        let (left, top) = (self.px(x), self.px(y));
+        let (right, bottom) = (self.px(x + width), self.px(y + height));
+
+        Rect::new(left, top, (right - left).max(0) as u32, (bottom - top).max(0) as u32)
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Whether the bar being drawn is horizontal or vertical.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Modules drawn so far this frame, for hit-testing or placing tooltips.
+    pub fn module_slots(&self) -> &[ModuleSlot] {
+        &self.frame.slots
+    }
+
+    /// Width in logical pixels.
+    pub fn width(&self) -> u32 {
+        (self.width as f32 / self.scale).round() as u32
+    }
+
+    /// Height in logical pixels.
+    pub fn height(&self) -> u32 {
+        (self.height as f32 / self.scale).round() as u32
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((self.x + x) + (self.y + y) * self.stride) as usize
+    }
+
+    /// Writes a pixel that is known to be inside the clip, damaging it only if it changes.
+    fn put(&mut self, x: u32, y: u32, color: u32) {
+        let index = self.index(x, y);
+        if self.pixels[index] != color {
+            self.pixels[index] = color;
+            self.frame.damage.mark(self.x + x, self.y + y);
         }
     }
 
-    pub fn fill(&mut self, color: u32) {
-        self.fill_rect(0, 0, self.width, self.height, color);
+    /// Fills the device pixels covering the logical pixel at `x`, `y`.
+    pub fn set_pixel(&mut self, x: f32, y: f32, color: u32) {
+        self.fill_rect(x, y, 1.0, 1.0, color);
     }
 
-    pub fn draw_line(&mut self, x0: u32, y0: u32, x1: u32, y1: u32, color: u32) {
-        let dx = (x1 as i32 - x0 as i32).abs();
-        let dy = -(y1 as i32 - y0 as i32).abs();
-        let sx = if x0 < x1 { 1 } else { -1 };
-        let sy = if y0 < y1 { 1 } else { -1 };
-        let mut err = dx + dy;
+    /// Outlines a rectangle with a line `stroke_width` wide inside it.
+    pub fn draw_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: u32) {
+        let rect = self.to_device(x, y, width, height);
+        if rect.is_empty() {
+            return;
+        }
 
-        let (mut x, mut y) = (x0 as i32, y0 as i32);
+        let thickness = self.px(self.stroke_width).max(1) as u32;
+        let thickness = thickness.min(rect.width).min(rect.height);
+        let right = rect.x + (rect.width - thickness) as i32;
+        let bottom = rect.y + (rect.height - thickness) as i32;
 
-        while x != x1 as i32 || y != y1 as i32 {
-            self.set_pixel(x as u32, y as u32, color);
-            let e2 = 2 * err;
-            if e2 >= dy {
-                err += dy;
-                x += sx;
-            }
-            if e2 <= dx {
-                err += dx;
-                y += sy;
-            }
+        // The sides stop short of the top and bottom so that translucent corners are not drawn twice
+        let side = rect.height.saturating_sub(thickness * 2);
+        let top = rect.y + thickness as i32;
+
+        self.fill_rect_px(Rect::new(rect.x, rect.y, rect.width, thickness), color);
+        if bottom > rect.y {
+            self.fill_rect_px(Rect::new(rect.x, bottom, rect.width, thickness), color);
         }
+        self.fill_rect_px(Rect::new(rect.x, top, thickness, side), color);
+        if right > rect.x {
+            self.fill_rect_px(Rect::new(right, top, thickness, side), color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: u32) {
+        let rect = self.to_device(x, y, width, height);
+        self.fill_rect_px(rect, color);
     }
 
-    pub fn draw_oval(&mut self, cx: u32, cy: u32, width: u32, height: u32, color: u32) {
-        let rx = width / 2;
-        let ry = height / 2;
-        let cx = cx + rx;
-        let cy = cy + ry;
-
-        for y in 0..height {
-            for x in 0..width {
-                let dx = x as i32 - rx as i32;
-                let dy = y as i32 - ry as i32;
-                if dx * dx * ry as i32 * ry as i32 + dy * dy * rx as i32 * rx as i32
-                    == (rx * ry) as i32 * (rx * ry) as i32
-                {
-                    self.set_pixel(cx + dx as u32, cy + dy as u32, color);
+    fn fill_rect_px(&mut self, rect: Rect, color: u32) {
+        let rect = rect.intersection(&self.clip);
+        if rect.is_empty() {
+            return;
+        }
+
+        let (x, y) = (rect.x as u32, rect.y as u32);
+        let x_end = x + rect.width;
+        let y_end = y + rect.height;
+
+        let color = premultiply(color);
+        let color = match self.blend_mode {
+            BlendMode::Source => color,
+            BlendMode::SourceOver if color >> 24 == 0xFF => color,
+            BlendMode::Clear => 0,
+            _ => {
+                // The result depends on each pixel
+                for j in y..y_end {
+                    for i in x..x_end {
+                        self.blend_px(i, j, color, 255);
+                    }
                 }
+                return;
             }
-        }
-    }
+        };
+
+        for j in y..y_end {
+            let row = self.index(0, j);
 
-    pub fn fill_oval(&mut self, cx: u32, cy: u32, width: u32, height: u32, color: u32) {
-        let rx = width / 2;
-        let ry = height / 2;
-        let cx = cx + rx;
-        let cy = cy + ry;
-
-        for y in 0..height {
-            for x in 0..width {
-                let dx = x as i32 - rx as i32;
-                let dy = y as i32 - ry as i32;
-                if dx * dx * ry as i32 * ry as i32 + dy * dy * rx as i32 * rx as i32
-                    <= (rx * ry) as i32 * (rx * ry) as i32
-                {
-                    self.set_pixel(cx + dx as u32, cy + dy as u32, color);
+            // Compare and fill tile by tile so that only tiles that change get damaged
+            let mut start = x;
+            while start < x_end {
+                let tile_end = ((self.x + start) / TILE_SIZE + 1) * TILE_SIZE - self.x;
+                let end = tile_end.min(x_end);
+
+                let span = &mut self.pixels[row + start as usize..row + end as usize];
+                if span.iter().any(|&pixel| pixel != color) {
+                    span.fill(color);
+                    self.frame.damage.mark(self.x + start, self.y + j);
                 }
+
+                start = end;
             }
         }
     }
 
+    /// Replaces everything inside the clip with `color`, whatever the blend mode. The canvas holds the
+    /// previous frame, which blending a translucent background over would build up frame after frame.
+    pub fn fill(&mut self, color: u32) {
+        let mode = std::mem::replace(&mut self.blend_mode, BlendMode::Source);
+        self.fill_rect_px(self.clip, color);
+        self.blend_mode = mode;
+    }
+
+    /// Draws a line `stroke_width` wide through the centers of the logical pixels at both ends, with square caps.
+    pub fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: u32) {
+        let scale = self.scale as f64;
+        let half = self.stroke_width as f64 * scale / 2.0;
+        let start = ((x0 as f64 + 0.5) * scale, (y0 as f64 + 0.5) * scale);
+        let end = ((x1 as f64 + 0.5) * scale, (y1 as f64 + 0.5) * scale);
+
+        // Only the part that can reach the clip is walked, so that far away ends cost nothing
+        let margin = half + 1.0;
+        let reach = (
+            self.clip.x as f64 - margin,
+            self.clip.y as f64 - margin,
+            self.clip.right() as f64 + margin,
+            self.clip.bottom() as f64 + margin,
+        );
+        let Some((start, end)) = clip_segment(start, end, reach) else {
+            return;
+        };
+
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = dx.hypot(dy);
+        let (ux, uy) = if length > 0.0 { (dx / length, dy / length) } else { (1.0, 0.0) };
+
+        // The caps stick out diagonally from the ends by up to half the width times the square root of two
+        let pad = half * std::f64::consts::SQRT_2;
+        let bounds = pixel_bounds(
+            start.0.min(end.0) - pad,
+            start.1.min(end.1) - pad,
+            start.0.max(end.0) + pad,
+            start.1.max(end.1) + pad,
+        );
+
+        self.fill_coverage(bounds, color, |x, y| {
+            let (vx, vy) = (x - start.0, y - start.1);
+            let along = vx * ux + vy * uy;
+            let across = (vx * uy - vy * ux).abs();
+
+            fill_coverage((-half - along).max(along - length - half).max(across - half))
+        });
+    }
+
+    /// Outlines the ellipse inscribed in the rectangle, with a line `stroke_width` wide inside it.
+    pub fn draw_oval(&mut self, x: f32, y: f32, width: f32, height: f32, color: u32) {
+        self.draw_arc(x, y, width, height, 0.0, 360.0, color);
+    }
+
+    pub fn fill_oval(&mut self, x: f32, y: f32, width: f32, height: f32, color: u32) {
+        self.fill_arc(x, y, width, height, 0.0, 360.0, color);
+    }
+
+    /// Outlines the part of the ellipse inscribed in the rectangle that starts at `start_angle` and
+    /// spans `sweep_angle`, with a line `stroke_width` wide inside it. Angles are in degrees, 0 pointing
+    /// right and positive ones going counterclockwise.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_arc(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+        color: u32,
+    ) {
+        let Some(shape) = self.device_shape(x, y, width, height, width / 2.0, height / 2.0) else {
+            return;
+        };
+
+        let stroke = self.stroke_width as f64 * self.scale as f64;
+        let wedge = Wedge::new(&shape, start_angle, sweep_angle);
+
+        self.fill_coverage(shape.bounds(), color, |x, y| {
+            let distance = shape.distance(x, y);
+            (fill_coverage(distance) - fill_coverage(distance + stroke)) * wedge.coverage(x, y)
+        });
+    }
+
+    /// Fills the pie slice of the ellipse inscribed in the rectangle, see `draw_arc`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_arc(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+        color: u32,
+    ) {
+        let Some(shape) = self.device_shape(x, y, width, height, width / 2.0, height / 2.0) else {
+            return;
+        };
+
+        let wedge = Wedge::new(&shape, start_angle, sweep_angle);
+
+        self.fill_coverage(shape.bounds(), color, |x, y| {
+            fill_coverage(shape.distance(x, y)) * wedge.coverage(x, y)
+        });
+    }
+
+    /// Outlines a rectangle whose corners are rounded by arcs `arc_width` wide and `arc_height` tall,
+    /// which are shrunk to fit, with a line `stroke_width` wide inside it.
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_rounded_rect(
         &mut self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-        arc_width: u32,
-        arc_height: u32,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        arc_width: f32,
+        arc_height: f32,
         color: u32,
     ) {
-        self.draw_line(x + arc_width, y, x + width - arc_width, y, color);
-        self.draw_line(x + arc_width, y + height - 1, x + width - arc_width, y + height - 1, color);
-        self.draw_line(x, y + arc_height, x, y + height - arc_height, color);
-        self.draw_line(x + width - 1, y + arc_height, x + width - 1, y + height - arc_height, color);
-        self.set_pixel(x + arc_width - 1, y + arc_height - 1, color);
-        self.set_pixel(x + width - arc_width, y + arc_height - 1, color);
-        self.set_pixel(x + arc_width - 1, y + height - arc_height, color);
-        self.set_pixel(x + width - arc_width, y + height - arc_height, color);
+        let Some(shape) = self.device_shape(x, y, width, height, arc_width, arc_height) else {
+            return;
+        };
+
+        let stroke = self.stroke_width as f64 * self.scale as f64;
+
+        self.fill_coverage(shape.bounds(), color, |x, y| {
+            let distance = shape.distance(x, y);
+            fill_coverage(distance) - fill_coverage(distance + stroke)
+        });
     }
 
+    /// Fills a rectangle with corners rounded by `radius`, which is shrunk to fit.
     pub fn fill_rounded_rect(
         &mut self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-        radius: u32,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        radius: f32,
         color: u32,
     ) {
-        if radius == 0 {
+        if radius <= 0.0 {
             self.fill_rect(x, y, width, height, color);
             return;
         }
 
-        let radius = radius.min(width / 2).min(height / 2);
-
-        self.fill_oval(x, y, radius * 2, radius * 2, color);
-        self.fill_oval(x + width - radius * 2, y, radius * 2, radius * 2, color);
-        self.fill_oval(x, y + height - radius * 2, radius * 2, radius * 2, color);
-        self.fill_oval(
-            x + width - radius * 2,
-            y + height - radius * 2,
-            radius * 2,
-            radius * 2,
-            color,
-        );
+        let Some(shape) = self.device_shape(x, y, width, height, radius, radius) else {
+            return;
+        };
 
-        self.fill_rect(x + radius, y, width - radius * 2, radius, color);
-        self.fill_rect(
-            x + radius,
-            y + height - radius,
-            width - radius * 2,
-            radius,
-            color,
-        );
+        self.fill_coverage(shape.bounds(), color, |x, y| fill_coverage(shape.distance(x, y)));
+    }
 
-        self.fill_rect(x, y + radius, radius, height - radius * 2, color);
-        self.fill_rect(
-            x + width - radius,
-            y + radius,
-            radius,
-            height - radius * 2,
-            color,
-        );
+    /// Fills the inside of `path` by `rule`, open subpaths being closed by a straight line.
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule, color: u32) {
+        let polygons: Vec<_> = path.flatten(self.scale as f64).into_iter().map(|polyline| polyline.points).collect();
+        self.fill_polygons(&polygons, rule, color);
+    }
 
-        self.fill_rect(
-            x + radius,
-            y + radius,
-            width - radius * 2,
-            height - radius * 2,
-            color,
+    /// Strokes `path` with a line `stroke_width` wide centered on it, using the line cap, line join and dashes set.
+    pub fn draw_path(&mut self, path: &Path, color: u32) {
+        let scale = self.scale as f64;
+        let dashes: Vec<f64> = self.dashes.iter().map(|&length| length as f64 * scale).collect();
+
+        let polygons = path::stroke(
+            &path.flatten(scale),
+            self.stroke_width as f64 * scale,
+            self.line_cap,
+            self.line_join,
+            &dashes,
+            self.dash_offset as f64 * scale,
         );
+        self.fill_polygons(&polygons, FillRule::NonZero, color);
     }
 
-    fn blend_pixel(&self, foreground: u32, background: u32, alpha: u32) -> u32 {
-        let fg_a = (foreground >> 24) & 0xFF; // Alpha
-        let fg_r = (foreground >> 16) & 0xFF; // Red
-        let fg_g = (foreground >> 8) & 0xFF;  // Green
-        let fg_b = foreground & 0xFF;         // Blue
-    
-        let bg_color = background;
-        let bg_a = (bg_color >> 24) & 0xFF;
-        let bg_r = (bg_color >> 16) & 0xFF;
-        let bg_g = (bg_color >> 8) & 0xFF;
-        let bg_b = bg_color & 0xFF;
-    
-        let alpha = alpha as f32 / 255.0;
-        let fg_alpha = fg_a as f32 / 255.0 * alpha;
-        let bg_alpha = bg_a as f32 / 255.0 * (1.0 - fg_alpha);
-    
-        let out_a = (fg_alpha + bg_alpha) * 255.0;
-    
-        let out_r = ((fg_r as f32 * fg_alpha + bg_r as f32 * bg_alpha) / (fg_alpha + bg_alpha)) as u32;
-        let out_g = ((fg_g as f32 * fg_alpha + bg_g as f32 * bg_alpha) / (fg_alpha + bg_alpha)) as u32;
-        let out_b = ((fg_b as f32 * fg_alpha + bg_b as f32 * bg_alpha) / (fg_alpha + bg_alpha)) as u32;
-    
-        ((out_a as u32) << 24) | ((out_r as u32) << 16) | ((out_g as u32) << 8) | out_b as u32
+    /// Fills polygons given in device coordinates.
+    fn fill_polygons(&mut self, polygons: &[Vec<(f64, f64)>], rule: FillRule, color: u32) {
+        let color = premultiply(color);
+
+        path::rasterize(polygons, rule, self.clip, |x, y, coverage| {
+            let alpha = (coverage * 255.0).round() as u32;
+            if alpha > 0 {
+                self.blend_px(x as u32, y as u32, color, alpha);
+            }
+        });
     }
-    
 
+    /// The rectangle in device pixels with corners rounded by the given radii, `None` if it is empty.
+    fn device_shape(&self, x: f32, y: f32, width: f32, height: f32, radius_x: f32, radius_y: f32) -> Option<Shape> {
+        let scale = self.scale as f64;
+        let (half_width, half_height) = (width as f64 * scale / 2.0, height as f64 * scale / 2.0);
+        if !(half_width > 0.0 && half_height > 0.0) {
+            return None;
+        }
+
+        Some(Shape {
+            center_x: x as f64 * scale + half_width,
+            center_y: y as f64 * scale + half_height,
+            half_width,
+            half_height,
+            radius_x: (radius_x as f64 * scale).clamp(0.0, half_width),
+            radius_y: (radius_y as f64 * scale).clamp(0.0, half_height),
+        })
+    }
+
+    /// Blends `color` into the pixels of `bounds` inside the clip, in proportion to the share of
+    /// each pixel `coverage` reports covered when given the pixel's center in device coordinates.
+    fn fill_coverage(&mut self, bounds: Rect, color: u32, coverage: impl Fn(f64, f64) -> f64) {
+        let visible = bounds.intersection(&self.clip);
+        let color = premultiply(color);
+
+        for y in visible.y..visible.y + visible.height as i32 {
+            for x in visible.x..visible.x + visible.width as i32 {
+                let alpha = (coverage(x as f64 + 0.5, y as f64 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u32;
+                if alpha > 0 {
+                    self.blend_px(x as u32, y as u32, color, alpha);
+                }
+            }
+        }
+    }
+
+    /// Combines the premultiplied `color` with a pixel known to be inside the clip by the blend mode,
+    /// `coverage` being how much of the pixel the color covers, out of 255.
+    fn blend_px(&mut self, x: u32, y: u32, color: u32, coverage: u32) {
+        let replaces = coverage == 255
+            && match self.blend_mode {
+                BlendMode::Source => true,
+                BlendMode::SourceOver => color >> 24 == 0xFF,
+                BlendMode::Multiply | BlendMode::Clear => false,
+            };
+        if replaces {
+            self.put(x, y, color);
+            return;
+        }
+
+        let background = self.pixels[self.index(x, y)];
+        self.put(x, y, composite(self.blend_mode, color, background, coverage));
+    }
+
+    /// Draws `c` with its baseline at `y`, `size` being the font size in logical pixels.
     pub fn draw_char(
         &mut self,
-        x: u32,
-        y: u32,
+        x: f32,
+        y: f32,
+        c: char,
+        color: u32,
+        font: &Font,
+        size: f32,
+    ) {
+        self.draw_char_px(self.px(x), self.px(y), c, color, font, size * self.scale);
+    }
+
+    fn draw_char_px(
+        &mut self,
+        x: i32,
+        y: i32,
         c: char,
         color: u32,
         font: &Font,
         size: f32,
     ) {
         let (metrics, bitmap) = font.rasterize(c, size);
+        let color = premultiply(color);
     
         let baseline_offset = metrics.height as i32 + metrics.ymin;
     
         for row in 0..metrics.height {
             for col in 0..metrics.width {
-                let pixel_x = x + col as u32;
-                let pixel_y = (y as i32 + row as i32 - baseline_offset) as u32;
+                let pixel_x = x + col as i32;
+                let pixel_y = y + row as i32 - baseline_offset;
     
-                if pixel_x >= self.width || pixel_y as u32 >= self.height {
+                if !self.clip_contains(pixel_x, pixel_y) {
                     continue;
                 }
     
                 let alpha = bitmap[row * metrics.width + col] as u32;
                 if alpha > 0 {
-                    let mut pixels = self.pixels.lock().unwrap();
-                    let blended_color = self.blend_pixel(color, pixels.clone()[(pixel_x + pixel_y * self.stride + self.offset) as usize], alpha);
-                    pixels[(pixel_x + pixel_y * self.stride + self.offset) as usize] = blended_color; // self.set_pixel(pixel_x, pixel_y as u32, blended_color);
+                    self.blend_px(pixel_x as u32, pixel_y as u32, color, alpha);
                 }
             }
         }
@@ -270,44 +710,528 @@ impl Canvas {
 
     pub fn draw_string(
         &mut self,
-        x: u32,
-        y: u32,
+        x: f32,
+        y: f32,
         text: &str,
         color: u32,
         font: &Font,
         size: f32,
     ) {
-        let mut cursor_x = x;
+        // Advance in device pixels so glyphs are spaced for the size they are rasterized at
+        let size = size * self.scale;
+        let y = self.px(y);
+
+        let mut cursor_x = self.px(x);
         for c in text.chars() {
-            let (metrics, _) = font.rasterize(c, size);
-            self.draw_char(cursor_x, y, c, color, font, size);
-            cursor_x += metrics.advance_width as u32;
+            let metrics = font.metrics(c, size);
+            self.draw_char_px(cursor_x, y, c, color, font, size);
+            cursor_x += metrics.advance_width as i32;
         }
     }    
 
+    /// Draws `text` turned clockwise so it reads from top to bottom, in a box
+    /// `text_height` wide and `text_width` tall whose top left corner is at `x`, `y`.
+    pub fn draw_string_rotated(
+        &mut self,
+        x: f32,
+        y: f32,
+        text: &str,
+        color: u32,
+        font: &Font,
+        size: f32,
+    ) {
+        let size = size * self.scale;
+        let ascent = font.horizontal_line_metrics(size).map_or(size, |metrics| metrics.ascent);
+        let line_height = Self::text_height(font, size) as i32;
+
+        let (x, y) = (self.px(x), self.px(y));
+        let color = premultiply(color);
+
+        // Along the text, which runs down the canvas
+        let mut cursor = 0;
+        for c in text.chars() {
+            let (metrics, bitmap) = font.rasterize(c, size);
+            let top = ascent as i32 - (metrics.height as i32 + metrics.ymin);
+
+            for row in 0..metrics.height {
+                for col in 0..metrics.width {
+                    let alpha = bitmap[row * metrics.width + col] as u32;
+                    if alpha == 0 {
+                        continue;
+                    }
+
+                    // Rows of the glyph become columns counted from the right edge of the box
+                    let pixel_x = x + line_height - 1 - (top + row as i32);
+                    let pixel_y = y + cursor + metrics.xmin + col as i32;
+
+                    if !self.clip_contains(pixel_x, pixel_y) {
+                        continue;
+                    }
+
+                    self.blend_px(pixel_x as u32, pixel_y as u32, color, alpha);
+                }
+            }
+
+            cursor += metrics.advance_width as i32;
+        }
+    }
+
+    /// Horizontal advance of `text`, in pixels.
+    pub fn text_width(text: &str, font: &Font, size: f32) -> u32 {
+        text.chars()
+            .map(|c| font.metrics(c, size).advance_width as u32)
+            .sum()
+    }
+
+    /// Height of a line of text from the lowest descender to the highest ascender, in pixels.
+    pub fn text_height(font: &Font, size: f32) -> u32 {
+        font.horizontal_line_metrics(size)
+            .map_or(size, |metrics| metrics.ascent - metrics.descent)
+            .ceil() as u32
+    }
+
+    /// Baseline that vertically centers text of the given size on this canvas.
+    pub fn text_baseline(&self, font: &Font, size: f32) -> f32 {
+        match font.horizontal_line_metrics(size) {
+            Some(metrics) => {
+                let text_height = metrics.ascent - metrics.descent;
+                ((self.height() as f32 - text_height) / 2.0 + metrics.ascent).floor()
+            }
+            None => self.height() as f32,
+        }
+    }
+
     pub fn draw_modules(&mut self, modules: &Modules, position: ModulePosition) {
+        let empty = Modules::new();
         match position {
-            ModulePosition::Left => {
-                let mut cursor_x = 0;
-                for module in &modules.modules {
-                    let width = module.get_width();
-                    let mut canvas = self.subcanvas(cursor_x, 0, width, self.height);
-                    
-                    module.draw(&mut canvas);
-
-                    cursor_x += width;
-                }
+            ModulePosition::Left => self.draw_module_groups(modules, &empty, &empty),
+            ModulePosition::Center => self.draw_module_groups(&empty, modules, &empty),
+            ModulePosition::Right => self.draw_module_groups(&empty, &empty, modules),
+        }
+    }
+
+    /// Lays out three groups of modules along the canvas, see `layout::layout`.
+    /// On vertical bars the groups go from top to bottom.
+    pub fn draw_module_groups(&mut self, left: &Modules, center: &Modules, right: &Modules) {
+        let groups = [left, center, right];
+        let vertical = self.orientation == Orientation::Vertical;
+
+        let (length, thickness) = if vertical {
+            (self.height(), self.width())
+        } else {
+            (self.width(), self.height())
+        };
+
+        for mut placement in layout::layout(groups, length, thickness, self.orientation) {
+            if vertical {
+                placement.rect = placement.rect.transposed();
+                placement.content = placement.content.transposed();
             }
-            _ => unimplemented!()
+
+            let entry = &groups[placement.group].modules[placement.index];
+            let content = placement.content;
+
+            let mut canvas = self.subcanvas(content.x as u32, content.y as u32, content.width, content.height);
+            entry.module.borrow().draw(&mut canvas);
+
+            self.frame.slots.push(ModuleSlot {
+                module: entry.module.clone(),
+                rect: Rect::new(
+                    self.logical_x + placement.rect.x,
+                    self.logical_y + placement.rect.y,
+                    placement.rect.width,
+                    placement.rect.height,
+                ),
+            });
+        }
+    }
+}
+
+/// Rounds a device coordinate to a pixel, see `MAX_COORD`.
+fn device(value: f32) -> i32 {
+    value.round().clamp(-MAX_COORD, MAX_COORD) as i32
+}
+
+/// Multiplies the color channels of a straight ARGB color by its alpha.
+fn premultiply(color: u32) -> u32 {
+    let alpha = color >> 24;
+    match alpha {
+        0xFF => color,
+        0 => 0,
+        _ => map_channels(color & 0xFFFFFF, 0, |channel, _| mul(channel, alpha)) | alpha << 24,
+    }
+}
+
+/// Combines the premultiplied `source` with `destination` by `mode`, then mixes the result
+/// with `destination` by `coverage`, out of 255.
+fn composite(mode: BlendMode, source: u32, destination: u32, coverage: u32) -> u32 {
+    let source_alpha = source >> 24;
+    let destination_alpha = destination >> 24;
+
+    let blended = match mode {
+        BlendMode::Source => source,
+        BlendMode::Clear => 0,
+        BlendMode::SourceOver => map_channels(source, destination, |s, d| s + mul(d, 255 - source_alpha)),
+        BlendMode::Multiply => map_channels(source, destination, |s, d| {
+            (mul(s, d) + mul(s, 255 - destination_alpha) + mul(d, 255 - source_alpha)).min(255)
+        }),
+    };
+
+    if coverage >= 255 {
+        return blended;
+    }
+
+    map_channels(blended, destination, |b, d| (b * coverage + d * (255 - coverage) + 127) / 255)
+}
+
+/// Applies `f` to the channels of `a` and `b` pairwise, alpha included.
+fn map_channels(a: u32, b: u32, f: impl Fn(u32, u32) -> u32) -> u32 {
+    (0..4).fold(0, |color, channel| {
+        color | f(channel_of(a, channel), channel_of(b, channel)) << (channel * 8)
+    })
+}
+
+fn channel_of(color: u32, channel: u32) -> u32 {
+    (color >> (channel * 8)) & 0xFF
+}
+
+/// `a` times `b` divided by 255, rounded.
+fn mul(a: u32, b: u32) -> u32 {
+    let product = a * b + 128;
+    (product + (product >> 8)) >> 8
+}
+
+/// A rectangle with elliptical corners in device coordinates, which covers ovals as well.
+struct Shape {
+    center_x: f64,
+    center_y: f64,
+    half_width: f64,
+    half_height: f64,
+    /// At most the half sizes, reaching them makes the shape an ellipse
+    radius_x: f64,
+    radius_y: f64,
+}
+
+impl Shape {
+    fn bounds(&self) -> Rect {
+        pixel_bounds(
+            self.center_x - self.half_width,
+            self.center_y - self.half_height,
+            self.center_x + self.half_width,
+            self.center_y + self.half_height,
+        )
+    }
+
+    /// Approximate signed distance from `x`, `y` to the outline, negative inside.
+    fn distance(&self, x: f64, y: f64) -> f64 {
+        // By symmetry only the bottom right quarter matters
+        let x = (x - self.center_x).abs();
+        let y = (y - self.center_y).abs();
+
+        // Centers of the corner arcs
+        let corner_x = self.half_width - self.radius_x;
+        let corner_y = self.half_height - self.radius_y;
+
+        if x > corner_x && y > corner_y && self.radius_x > 0.0 && self.radius_y > 0.0 {
+            ellipse_distance(x - corner_x, y - corner_y, self.radius_x, self.radius_y)
+        } else {
+            (x - self.half_width).max(y - self.half_height)
         }
     }
 }
 
-impl Clone for Canvas {
-    fn clone (&self) -> Self {
+/// The angles an arc spans, as a coverage that fades over a pixel at its straight edges.
+struct Wedge {
+    center: (f64, f64),
+    /// Directions of the edges at the start and end angles, pointing away from the center
+    start: (f64, f64),
+    end: (f64, f64),
+    start_angle: f64,
+    sweep_angle: f64,
+    radius_x: f64,
+    radius_y: f64,
+}
+
+impl Wedge {
+    fn new(shape: &Shape, start_angle: f32, sweep_angle: f32) -> Self {
+        // Going clockwise from the start is going counterclockwise from the end
+        let (start_angle, sweep_angle) = if sweep_angle < 0.0 {
+            (start_angle as f64 + sweep_angle as f64, -sweep_angle as f64)
+        } else {
+            (start_angle as f64, sweep_angle as f64)
+        };
+
+        // Angles are measured on the circle the ellipse is stretched from, with y pointing up
+        let direction = |angle: f64| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let (dx, dy) = (shape.half_width * cos, -shape.half_height * sin);
+            let length = dx.hypot(dy);
+            (dx / length, dy / length)
+        };
+
         Self {
-            pixels: self.pixels.clone(),
-            ..*self
+            center: (shape.center_x, shape.center_y),
+            start: direction(start_angle),
+            end: direction(start_angle + sweep_angle),
+            start_angle: start_angle.rem_euclid(360.0),
+            sweep_angle,
+            radius_x: shape.half_width,
+            radius_y: shape.half_height,
+        }
+    }
+
+    fn coverage(&self, x: f64, y: f64) -> f64 {
+        if self.sweep_angle >= 360.0 {
+            return 1.0;
+        }
+
+        let (dx, dy) = (x - self.center.0, y - self.center.1);
+        let angle = (-dy / self.radius_y).atan2(dx / self.radius_x).to_degrees();
+        let inside = (angle - self.start_angle).rem_euclid(360.0) <= self.sweep_angle;
+
+        let edge_distance = |(ux, uy): (f64, f64)| {
+            if dx * ux + dy * uy <= 0.0 {
+                dx.hypot(dy)
+            } else {
+                (dx * uy - dy * ux).abs()
+            }
+        };
+        let distance = edge_distance(self.start).min(edge_distance(self.end));
+
+        fill_coverage(if inside { -distance } else { distance })
+    }
+}
+
+/// Approximate signed distance from `x`, `y` to an ellipse centered on the origin, exact for circles.
+fn ellipse_distance(x: f64, y: f64, radius_x: f64, radius_y: f64) -> f64 {
+    let k0 = (x / radius_x).hypot(y / radius_y);
+    let k1 = (x / (radius_x * radius_x)).hypot(y / (radius_y * radius_y));
+
+    if k1 == 0.0 {
+        return -radius_x.min(radius_y);
+    }
+
+    k0 * (k0 - 1.0) / k1
+}
+
+/// How much of a pixel a shape covers, given the signed distance from the pixel's center to its outline.
+fn fill_coverage(distance: f64) -> f64 {
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// The pixels touched by something spanning `left` to `right` and `top` to `bottom` in device coordinates.
+pub(crate) fn pixel_bounds(left: f64, top: f64, right: f64, bottom: f64) -> Rect {
+    let limit = MAX_COORD as f64;
+    let left = left.floor().clamp(-limit, limit) as i32;
+    let top = top.floor().clamp(-limit, limit) as i32;
+    let right = right.ceil().clamp(-limit, limit) as i32;
+    let bottom = bottom.ceil().clamp(-limit, limit) as i32;
+
+    Rect::new(left, top, (right - left).max(0) as u32, (bottom - top).max(0) as u32)
+}
+
+/// Cuts the line from `start` to `end` to the area from `left`, `top` to `right`, `bottom`,
+/// Liang-Barsky style. Returns `None` if it misses.
+fn clip_segment(
+    start: (f64, f64),
+    end: (f64, f64),
+    (left, top, right, bottom): (f64, f64, f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    let (x0, y0) = start;
+    let (dx, dy) = (end.0 - x0, end.1 - y0);
+
+    // The part of the line kept, as fractions of the way from `start` to `end`
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for (p, q) in [(-dx, x0 - left), (dx, right - x0), (-dy, y0 - top), (dy, bottom - y0)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+
+    if t0 > t1 {
+        return None;
+    }
+
+    let point = |t: f64| (x0 + t * dx, y0 + t * dy);
+    Some((point(t0), point(t1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn premultiplies_straight_colors() {
+        assert_eq!(premultiply(0x80FF4000), 0x80802000);
+        assert_eq!(premultiply(0xFF123456), 0xFF123456);
+        assert_eq!(premultiply(0x00FFFFFF), 0);
+    }
+
+    #[test]
+    fn multiplies_with_rounding() {
+        for a in 0..=255 {
+            for b in 0..=255 {
+                assert_eq!(mul(a, b), (a * b * 2 + 255) / 510, "{a} * {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn source_over_translucent_color() {
+        let red = premultiply(0x80FF0000);
+
+        assert_eq!(composite(BlendMode::SourceOver, red, 0xFF0000FF, 255), 0xFF80007F);
+        assert_eq!(composite(BlendMode::SourceOver, red, 0, 255), red);
+    }
+
+    #[test]
+    fn multiply_by_white_keeps_pixels() {
+        for pixel in [0xFF808080, 0xFF123456, 0xFF000000] {
+            assert_eq!(composite(BlendMode::Multiply, 0xFFFFFFFF, pixel, 255), pixel);
+        }
+        assert_eq!(composite(BlendMode::Multiply, 0xFF808080, 0xFFFFFFFF, 255), 0xFF808080);
+    }
+
+    #[test]
+    fn clear_makes_pixels_transparent() {
+        assert_eq!(composite(BlendMode::Clear, 0xFFFFFFFF, 0xFF123456, 255), 0);
+    }
+
+    #[test]
+    fn fill_replaces_previous_frame() {
+        let mut pixels = vec![0; 64];
+        for _ in 0..3 {
+            let mut frame = Frame::default();
+            let mut canvas = Canvas::new(&mut pixels, 8, 8, 1.0, Orientation::Horizontal, &mut frame);
+            canvas.set_blend_mode(BlendMode::Multiply);
+            canvas.fill(0x80FF0000);
+        }
+
+        assert!(pixels.iter().all(|&pixel| pixel == 0x80800000));
+    }
+
+    const BACKGROUND: u32 = 0xFF0000FF;
+
+    /// Every pixel of a `width` by `height` buffer not inside `rect` still has the background color.
+    fn assert_untouched_outside(pixels: &[u32], width: u32, rect: Rect) {
+        for (index, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            if !rect.contains(x as f64 + 0.5, y as f64 + 0.5) {
+                assert_eq!(pixel, BACKGROUND, "pixel {x},{y} outside {rect:?} was drawn to");
+            }
+        }
+    }
+
+    /// Throws shapes at the canvas that reach well past each of its edges.
+    fn draw_everywhere(canvas: &mut Canvas, font: Option<&Font>) {
+        let color = 0xFFFF0000;
+
+        canvas.fill_rect(-50.0, -50.0, 500.0, 500.0, color);
+        canvas.draw_rect(-3.0, -3.0, 100.0, 100.0, color);
+        canvas.set_pixel(-1.0, -1.0, color);
+        canvas.set_pixel(1000.0, 5.0, color);
+        canvas.draw_line(-20.0, -20.0, 200.0, 60.0, color);
+        canvas.set_stroke_width(7.0);
+        canvas.draw_oval(-10.0, -10.0, 60.0, 40.0, color);
+        canvas.fill_oval(20.0, -15.0, 30.0, 30.0, color);
+        canvas.draw_arc(-30.0, 2.0, 90.0, 90.0, 0.0, 270.0, color);
+        canvas.fill_arc(5.0, 5.0, 40.0, 40.0, 45.0, 180.0, color);
+        canvas.draw_rounded_rect(-5.0, -5.0, 60.0, 30.0, 8.0, 8.0, color);
+        canvas.fill_rounded_rect(-5.0, 3.0, 60.0, 30.0, 6.0, color);
+
+        let mut path = Path::new();
+        path.move_to(-40.0, 5.0).line_to(80.0, -30.0).quad_to(90.0, 50.0, 10.0, 70.0).close();
+        canvas.fill_path(&path, FillRule::NonZero, color);
+        canvas.draw_path(&path, color);
+
+        if let Some(font) = font {
+            canvas.draw_string(-8.0, 6.0, "Wg|@", color, font, 24.0);
+            canvas.draw_string(4.0, 40.0, "MMMM", color, font, 30.0);
+            canvas.draw_string_rotated(-6.0, -10.0, "Wg|@", color, font, 24.0);
+        }
+    }
+
+    fn font() -> Option<Font> {
+        let bytes = std::fs::read("/usr/share/fonts/TTF/HackNerdFontMono-Regular.ttf").ok()?;
+        Font::from_bytes(bytes, fontdue::FontSettings::default()).ok()
+    }
+
+    #[test]
+    fn subcanvas_keeps_to_its_region() {
+        let font = font();
+
+        for scale in [1.0, 1.5] {
+            let mut pixels = vec![BACKGROUND; 40 * 30];
+            let mut frame = Frame::default();
+            let mut canvas = Canvas::new(&mut pixels, 40, 30, scale, Orientation::Horizontal, &mut frame);
+
+            draw_everywhere(&mut canvas.subcanvas(6, 4, 12, 8), font.as_ref());
+            // Partly past the bottom right corner, cut to the canvas
+            draw_everywhere(&mut canvas.subcanvas(24, 16, 40, 40), font.as_ref());
+
+            let region = |x: f32, y: f32, width: f32, height: f32| {
+                pixel_bounds((x * scale) as f64, (y * scale) as f64, ((x + width) * scale) as f64, ((y + height) * scale) as f64)
+            };
+            let first = region(6.0, 4.0, 12.0, 8.0);
+            let second = region(24.0, 16.0, 40.0, 40.0);
+            assert_ne!(pixels[(first.y * 40 + first.x) as usize], BACKGROUND);
+            assert_ne!(pixels[40 * 30 - 1], BACKGROUND);
+
+            for (index, &pixel) in pixels.iter().enumerate() {
+                let (x, y) = ((index % 40) as f64 + 0.5, (index / 40) as f64 + 0.5);
+                if !first.contains(x, y) && !second.contains(x, y) {
+                    assert_eq!(pixel, BACKGROUND, "pixel {x},{y} at scale {scale} was drawn to");
+                }
+            }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn nested_clips_intersect() {
+        let font = font();
+        let mut pixels = vec![BACKGROUND; 40 * 30];
+        let mut frame = Frame::default();
+        let mut canvas = Canvas::new(&mut pixels, 40, 30, 1.0, Orientation::Horizontal, &mut frame);
+        let mut slot = canvas.subcanvas(5, 5, 20, 20);
+
+        slot.push_clip(2.0, 2.0, 10.0, 6.0);
+        slot.push_clip(-10.0, 4.0, 100.0, 100.0);
+        draw_everywhere(&mut slot, font.as_ref());
+        drop(slot);
+        assert_untouched_outside(&pixels, 40, Rect::new(7, 9, 10, 4));
+        assert!(pixels[11 * 40 + 10] != BACKGROUND);
+
+        let mut canvas = Canvas::new(&mut pixels, 40, 30, 1.0, Orientation::Horizontal, &mut frame);
+        let mut slot = canvas.subcanvas(5, 5, 20, 20);
+        slot.push_clip(2.0, 2.0, 10.0, 6.0);
+        slot.push_clip(-10.0, 4.0, 100.0, 100.0);
+        slot.pop_clip();
+        draw_everywhere(&mut slot, font.as_ref());
+        assert_untouched_outside(&pixels, 40, Rect::new(7, 7, 10, 6));
+
+        // A clip outside the slot lets nothing through, and popping more than was pushed keeps the slot's own
+        let mut pixels = vec![BACKGROUND; 40 * 30];
+        let mut canvas = Canvas::new(&mut pixels, 40, 30, 1.0, Orientation::Horizontal, &mut frame);
+        let mut slot = canvas.subcanvas(5, 5, 20, 20);
+        slot.push_clip(-30.0, -30.0, 20.0, 20.0);
+        draw_everywhere(&mut slot, font.as_ref());
+        slot.pop_clip();
+        slot.pop_clip();
+        slot.fill(0xFF00FF00);
+        assert_untouched_outside(&pixels, 40, Rect::new(5, 5, 20, 20));
+        assert!(pixels.iter().all(|&pixel| pixel == BACKGROUND || pixel == 0xFF00FF00));
+    }
+
+    #[test]
+    fn partial_coverage_mixes_with_pixel() {
+        assert_eq!(composite(BlendMode::SourceOver, 0xFFFFFFFF, 0xFF000000, 128), 0xFF808080);
+        assert_eq!(composite(BlendMode::Source, 0, 0xFFFFFFFF, 128), 0x7F7F7F7F);
+        assert_eq!(composite(BlendMode::Clear, 0, 0xFF808080, 0), 0xFF808080);
+    }
+}
@@ -1,132 +1,832 @@
-use crate::state::State;
-use crate::canvas::Canvas;
-use crate::bar::{Bar, BarPosition};
+use crate::state::{BarId, State};
+use crate::error::{Error, Result};
+use crate::canvas::{Canvas, Rect};
+use crate::buffer::BufferPool;
+use crate::bar::{Bar, BarConfig, BarOutput, BarSpec, DrawFn};
+use crate::output::Output;
+use crate::layout::Orientation;
+use crate::modules::ModuleHandle;
+use crate::input::{self, PointerEvent, TouchEvent, TouchGesture};
+use crate::event_loop::{self, EventSource, FdSource, Timer};
 
-use std::os::unix::io::AsFd;
-use std::io::Write;
-use std::io::Seek;
+use std::os::unix::io::{AsFd, BorrowedFd};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use nix::poll::{PollFd, PollFlags, PollTimeout};
 
 use wayland_client::{
-    protocol::*,
+    backend::WaylandError,
 };
 
+/// How often to try connecting again while waiting for the compositor to come back.
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(250);
+
+type ErrorHandler = Box<dyn FnMut(&Error)>;
+
+struct PointerFocus {
+    bar: BarId,
+    x: f64,
+    y: f64,
+}
+
+struct TouchPoint {
+    id: i32,
+    serial: u32,
+    module: ModuleHandle,
+    slot: Rect,
+    start: (f64, f64),
+    position: (f64, f64),
+    long_pressed: bool,
+}
+
+impl TouchPoint {
+    fn travel(&self) -> (f64, f64) {
+        (self.position.0 - self.start.0, self.position.1 - self.start.1)
+    }
+
+    fn is_stationary(&self) -> bool {
+        let (dx, dy) = self.travel();
+        dx.hypot(dy) <= input::TAP_SLOP
+    }
+
+    /// Where the finger went down, relative to the module's slot.
+    fn local_start(&self) -> (f64, f64) {
+        (self.start.0 - self.slot.x as f64, self.start.1 - self.slot.y as f64)
+    }
+}
+
 pub struct Client {
     state: State,
-    
+
+    connection: wayland_client::Connection,
     event_queue: wayland_client::EventQueue<State>,
     qh: wayland_client::QueueHandle<State>,
 
+    specs: Vec<BarSpec>,
     bars: Vec<Bar>,
-}
+    next_bar_id: BarId,
+    /// Spec and output of the bars the compositor closed, which are not recreated until the
+    /// outputs change, as the compositor would likely close them again right away
+    closed: Vec<(usize, Option<u32>)>,
 
-impl Client {
-    pub fn new() -> Self {
-        let connection = wayland_client::Connection::connect_to_env().unwrap();
-        let mut event_queue = connection.new_event_queue();
-        let qh = event_queue.handle();
+    sources: Vec<Box<dyn EventSource>>,
+    timers: Vec<Timer>,
 
-        let display = connection.display();
-        display.get_registry(&qh, ());
+    pointer: Option<PointerFocus>,
+    hovered: Option<ModuleHandle>,
+    touches: Vec<TouchPoint>,
 
-        let mut state = State {
-            running: true,
-            ..State::default()
-        };
+    reconnect_timeout: Option<Duration>,
+    error_handler: Option<ErrorHandler>,
+}
 
-        event_queue.roundtrip(&mut state).unwrap();
+impl Client {
+    /// Connects to the compositor named by `WAYLAND_DISPLAY`, failing if it lacks a protocol the bar needs.
+    pub fn new() -> Result<Self> {
+        let (connection, event_queue, state) = connect()?;
+        let qh = event_queue.handle();
 
-        Self {
+        Ok(Self {
             state,
+            connection,
             event_queue,
             qh,
+            specs: Vec::new(),
             bars: Vec::new(),
+            next_bar_id: 0,
+            closed: Vec::new(),
+            sources: Vec::new(),
+            timers: Vec::new(),
+
+            pointer: None,
+            hovered: None,
+            touches: Vec::new(),
+
+            reconnect_timeout: None,
+            error_handler: None,
+        })
+    }
+
+    /// Makes `start` reconnect when the connection to the compositor is lost, waiting up to
+    /// `timeout` for `WAYLAND_DISPLAY` to reappear, e.g. while the compositor restarts.
+    /// The bars are recreated on the new connection, keeping their modules. Off by default.
+    pub fn set_reconnect_timeout(&mut self, timeout: Option<Duration>) {
+        self.reconnect_timeout = timeout;
+    }
+
+    /// Calls `handler` with the errors the client gets over without stopping: a lost connection
+    /// it is reconnecting after, and a config that failed to reload and left the bars as they were.
+    /// Such errors are dropped by default.
+    pub fn set_error_handler<F: FnMut(&Error) + 'static>(&mut self, handler: F) {
+        self.error_handler = Some(Box::new(handler));
+    }
+
+    pub(crate) fn report(&mut self, error: &Error) {
+        if let Some(handler) = &mut self.error_handler {
+            handler(error);
+        }
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.state.outputs
+    }
+
+    pub fn add_bar<F: Fn(&mut Canvas) + 'static>(&mut self, config: BarConfig, draw: F) {
+        self.specs.push(BarSpec {
+            config,
+            draw: Rc::new(draw),
+            hidden: false,
+        });
+
+        self.sync_bars();
+    }
+
+    /// Replaces all bars, keeping the surfaces of bars whose config did not change
+    /// so that only those that moved or resized flicker.
+    pub fn set_bars(&mut self, bars: Vec<(BarConfig, DrawFn)>) {
+        let old_specs = std::mem::take(&mut self.specs);
+
+        // Index of the new spec each old spec lives on as
+        let mut kept: Vec<Option<usize>> = vec![None; old_specs.len()];
+        for (config, draw) in bars {
+            let unchanged = old_specs
+                .iter()
+                .enumerate()
+                .position(|(index, spec)| kept[index].is_none() && spec.config == config);
+
+            if let Some(index) = unchanged {
+                kept[index] = Some(self.specs.len());
+            }
+
+            // Bars that stay keep being hidden
+            let hidden = unchanged.is_some_and(|index| old_specs[index].hidden);
+            self.specs.push(BarSpec { config, draw, hidden });
+        }
+
+        self.closed = self.closed
+            .iter()
+            .filter_map(|&(spec, output)| Some((kept[spec]?, output)))
+            .collect();
+
+        let layers = &mut self.state.layers;
+        self.bars.retain_mut(|bar| match kept[bar.spec] {
+            Some(spec) => {
+                bar.spec = spec;
+                bar.draw = self.specs[spec].draw.clone();
+                if let Some(layer) = layers.get_mut(&bar.id) {
+                    layer.needs_redraw = true;
+                }
+                true
+            }
+            None => {
+                bar.destroy();
+                layers.remove(&bar.id);
+                false
+            }
+        });
+
+        self.sync_bars();
+    }
+
+    /// Shows or hides the bars with the given namespace, or all bars, toggling them if `visible` is `None`.
+    /// Returns how many bars matched.
+    pub fn set_visible(&mut self, namespace: Option<&str>, visible: Option<bool>) -> usize {
+        let mut matched = 0;
+        for spec in &mut self.specs {
+            if namespace.is_some_and(|namespace| spec.config.namespace != namespace) {
+                continue;
+            }
+
+            spec.hidden = !visible.unwrap_or(spec.hidden);
+            matched += 1;
+        }
+
+        self.sync_bars();
+        matched
+    }
+
+    /// Watches `source` in the event loop, calling `callback` whenever it becomes readable
+    /// until the callback returns `false`.
+    pub fn add_source<S, F>(&mut self, source: S, callback: F)
+    where
+        S: AsFd + 'static,
+        F: FnMut(&mut Client, &mut S) -> bool + 'static,
+    {
+        self.sources.push(Box::new(FdSource { source, callback }));
+    }
+
+    /// Calls `callback` once from the event loop as soon as `deadline` has passed.
+    pub fn add_timer<F: FnOnce(&mut Client) + 'static>(&mut self, deadline: Instant, callback: F) {
+        self.timers.push(Timer {
+            deadline,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Creates and destroys bars so that every spec has exactly one bar on each output it targets.
+    fn sync_bars(&mut self) {
+        if std::mem::take(&mut self.state.outputs_changed) {
+            self.closed.clear();
+        }
+
+        let closed: Vec<BarId> = self.state.layers
+            .iter()
+            .filter(|(_, layer)| layer.closed)
+            .map(|(id, _)| *id)
+            .collect();
+        self.closed.extend(self.bars.iter().filter(|bar| closed.contains(&bar.id)).map(|bar| (bar.spec, bar.output)));
+        // Showing a hidden bar again is worth another try
+        let specs = &self.specs;
+        self.closed.retain(|&(spec, _)| !specs[spec].hidden);
+
+        let mut wanted: Vec<(usize, Option<u32>)> = Vec::new();
+        for (index, spec) in self.specs.iter().enumerate().filter(|(_, spec)| !spec.hidden) {
+            match &spec.config.output {
+                BarOutput::All => {
+                    wanted.extend(
+                        self.state.outputs
+                            .iter()
+                            .filter(|output| output.ready)
+                            .map(|output| (index, Some(output.global_name)))
+                    );
+                }
+                // Without outputs the compositor has nowhere to put the bar and closes it
+                BarOutput::Focused if self.state.outputs.iter().any(|output| output.ready) => {
+                    wanted.push((index, None));
+                }
+                BarOutput::Focused => {}
+                BarOutput::Name(name) => {
+                    wanted.extend(
+                        self.state.outputs
+                            .iter()
+                            .filter(|output| output.ready && output.name() == Some(name.as_str()))
+                            .map(|output| (index, Some(output.global_name)))
+                    );
+                }
+            }
+        }
+
+        let layers = &mut self.state.layers;
+        self.bars.retain_mut(|bar| {
+            let keep = !closed.contains(&bar.id) && wanted.contains(&(bar.spec, bar.output));
+            if !keep {
+                bar.destroy();
+                layers.remove(&bar.id);
+            }
+            keep
+        });
+
+        for (spec, output) in wanted {
+            let exists = self.bars.iter().any(|bar| bar.spec == spec && bar.output == output);
+            if exists || self.closed.contains(&(spec, output)) {
+                continue;
+            }
+
+            self.create_bar(spec, output);
         }
     }
 
-    pub fn add_bar<F: Fn(&mut Canvas) -> () + 'static>(&mut self, position: BarPosition, height: u32, draw: F) {
-        let compositor = self
-            .state
-            .compositor
-            .as_ref()
-            .expect("Compositor not initialized");
+    fn create_bar(&mut self, spec: usize, output: Option<u32>) {
+        // Both are checked for when connecting
+        let (Some(compositor), Some(layer_shell)) = (&self.state.compositor, &self.state.layer_shell) else {
+            return;
+        };
+
+        let output = output.and_then(|global_name| {
+            self.state.outputs.iter().find(|output| output.global_name == global_name)
+        });
+
+        // Without an explicit output the compositor picks one, so guess its size from the first one
+        let size = output
+            .or(self.state.outputs.first())
+            .and_then(Output::logical_size)
+            .unwrap_or((1920, 1080));
 
-        let layer_shell = self
-            .state
-            .layer_shell
-            .as_ref()
-            .expect("Layer shell not initialized");
+        let id = self.next_bar_id;
+        self.next_bar_id += 1;
+        self.state.layers.insert(id, Default::default());
 
         let bar = Bar::new(
+            id,
             compositor,
             layer_shell,
-            position,
-            height,
-            draw,
+            spec,
+            &self.specs[spec],
+            output.map(|output| (output.global_name, &output.wl_output)),
+            size,
+            self.state.fractional_scale_manager.as_ref().zip(self.state.viewporter.as_ref()),
             &self.qh
         );
         self.bars.push(bar);
     }
 
-    fn render(&mut self) {
+    fn render(&mut self) -> Result<()> {
         for bar in &mut self.bars {
-            if !self.state.configured {
+            let Some(layer) = self.state.layers.get_mut(&bar.id).filter(|layer| layer.configured) else {
+                continue;
+            };
+
+            // Nothing changed, or the compositor has not shown the previous frame yet
+            if !layer.needs_redraw || layer.frame_pending {
                 continue;
             }
-    
+
+            let scale = match layer.preferred_scale.filter(|_| bar.viewport.is_some()) {
+                Some(scale) => scale as f32 / 120.0,
+                None => integer_scale(&self.state.outputs, &layer.outputs, bar.output) as f32,
+            };
+
+            // A zero dimension leaves the choice to us, so keep what was requested
+            bar.resize(
+                if layer.width == 0 { bar.width } else { layer.width },
+                if layer.height == 0 { bar.height } else { layer.height },
+                scale,
+            );
+
             if let Some(shm) = &self.state.shm {
-                let width = 1920;
-                let height = bar.height;
-                let stride = width * 4;
-                let size = stride * height;
-    
-                let tmpfile = bar.tmpfile.get_or_insert_with(|| {
-                    let tmpfile = tempfile::tempfile().unwrap();
-                    tmpfile.set_len(size as u64).unwrap();
-                    tmpfile
-                });
-    
-                let mut canvas = bar.canvas.get_or_insert_with(|| {
-                    let background_color = 0xFF000000u32;
-                    Canvas::new(width, height, background_color)
-                });
-    
+                let (width, height) = bar.buffer_size();
+
+                if bar.pool.is_none() {
+                    bar.pool = Some(BufferPool::new(shm, width, height, &self.qh)?);
+                }
+
+                let Some(pool) = &mut bar.pool else {
+                    continue;
+                };
+
+                // Both buffers are still in use, the release event will wake us up again
+                let Some(index) = pool.acquire() else {
+                    continue;
+                };
+
+                pool.prepare(index);
+
+                // Draw straight into the shared memory, the buffer is not read until attached
+                let orientation = if self.specs[bar.spec].config.position.is_vertical() {
+                    Orientation::Vertical
+                } else {
+                    Orientation::Horizontal
+                };
+
+                let mut canvas = Canvas::new(pool.pixels_mut(index), width, height, bar.scale, orientation, &mut bar.frame);
                 (bar.draw)(&mut canvas);
-    
-                let data = canvas.pixels.lock().unwrap();
-                tmpfile.rewind().unwrap();
-                tmpfile.write_all(bytemuck::cast_slice(&data.clone())).unwrap();
-    
-                let shm_pool = bar.shm_pool.get_or_insert_with(|| {
-                    shm.create_pool(tmpfile.as_fd(), size as i32, &self.qh, ())
-                });
-    
-                let buffer = bar.buffer.get_or_insert_with(|| {
-                    let buffer = shm_pool.create_buffer(
-                        0,
-                        width as i32,
-                        height as i32,
-                        stride as i32,
-                        wl_shm::Format::Argb8888,
-                        &self.qh,
-                        (),
-                    );
-                    buffer
-                });
-    
-                bar.base_surface.attach(Some(buffer), 0, 0);
+                bar.slots = std::mem::take(&mut bar.frame.slots);
+
+                // The buffer started out as a copy of the front buffer, so tiles that match it did not change
+                if let Some(front) = pool.front_pixels(index) {
+                    bar.frame.damage.retain_changed(front, pool.pixels(index), width);
+                }
+
+                layer.needs_redraw = false;
+
+                let damage = if pool.is_fresh() {
+                    vec![Rect::new(0, 0, width, height)]
+                } else if bar.frame.damage.is_empty() {
+                    // Identical to what is on screen, the buffer stays free for the next frame
+                    continue;
+                } else {
+                    bar.frame.damage.rects()
+                };
+
+                bar.base_surface.frame(&self.qh, bar.id);
+                pool.attach(index, &bar.base_surface, &damage);
                 bar.base_surface.commit();
+
+                layer.frame_pending = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_timers(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.timers)
+            .into_iter()
+            .partition(|timer| timer.deadline <= now);
+        self.timers = pending;
+
+        for timer in due {
+            (timer.callback)(self);
+        }
+    }
+
+    fn update_modules(&mut self) {
+        let now = Instant::now();
+
+        let mut changed: Vec<ModuleHandle> = Vec::new();
+        for bar in &self.bars {
+            for module in bar.slots.iter().map(|slot| &slot.module) {
+                if changed.iter().any(|other| Rc::ptr_eq(other, module)) {
+                    continue;
+                }
+
+                let due = module.borrow().next_update().is_some_and(|deadline| deadline <= now);
+                if due && module.borrow_mut().update() {
+                    changed.push(module.clone());
+                }
+            }
+        }
+
+        self.mark_changed(&changed);
+    }
+
+    /// The modules drawn on any bar in the last frame, each once.
+    pub fn shown_modules(&self) -> Vec<ModuleHandle> {
+        let mut modules: Vec<ModuleHandle> = Vec::new();
+        for module in self.bars.iter().flat_map(|bar| bar.slots.iter().map(|slot| &slot.module)) {
+            if !modules.iter().any(|other| Rc::ptr_eq(other, module)) {
+                modules.push(module.clone());
+            }
+        }
+
+        modules
+    }
+
+    /// Updates `modules` right away instead of when they are due, redrawing the bars they changed on.
+    pub fn refresh_modules(&mut self, modules: &[ModuleHandle]) {
+        let changed: Vec<ModuleHandle> = modules
+            .iter()
+            .filter(|module| module.borrow_mut().update())
+            .cloned()
+            .collect();
+
+        self.mark_changed(&changed);
+    }
+
+    /// Schedules a redraw of every bar showing one of `modules`.
+    pub fn mark_changed(&mut self, modules: &[ModuleHandle]) {
+        for bar in &self.bars {
+            if bar.slots.iter().any(|slot| modules.iter().any(|module| Rc::ptr_eq(module, &slot.module))) {
+                if let Some(layer) = self.state.layers.get_mut(&bar.id) {
+                    layer.needs_redraw = true;
+                }
+            }
+        }
+    }
+
+    /// The module under the pointer and the pointer position relative to its slot.
+    fn pointer_target(&self) -> Option<(ModuleHandle, f64, f64)> {
+        let pointer = self.pointer.as_ref()?;
+        let bar = self.bars.iter().find(|bar| bar.id == pointer.bar)?;
+        let slot = bar.module_at(pointer.x, pointer.y)?;
+
+        Some((slot.module.clone(), pointer.x - slot.rect.x as f64, pointer.y - slot.rect.y as f64))
+    }
+
+    fn update_hover(&mut self) {
+        let target = self.pointer_target().map(|(module, _, _)| module);
+        if target.as_ref().map(Rc::as_ptr) == self.hovered.as_ref().map(Rc::as_ptr) {
+            return;
+        }
+
+        if let Some(module) = self.hovered.take() {
+            module.borrow_mut().on_leave();
+            self.mark_changed(&[module]);
+        }
+
+        if let Some(module) = target {
+            module.borrow_mut().on_enter();
+            self.mark_changed(std::slice::from_ref(&module));
+            self.hovered = Some(module);
+        }
+    }
+
+    fn handle_pointer_events(&mut self) {
+        for event in std::mem::take(&mut self.state.pointer_events) {
+            match event {
+                PointerEvent::Enter { surface, x, y } => {
+                    self.pointer = self.bars
+                        .iter()
+                        .find(|bar| bar.base_surface == surface)
+                        .map(|bar| PointerFocus { bar: bar.id, x, y });
+                }
+                PointerEvent::Leave => {
+                    self.pointer = None;
+                }
+                PointerEvent::Motion { x, y } => {
+                    if let Some(pointer) = &mut self.pointer {
+                        pointer.x = x;
+                        pointer.y = y;
+                    }
+                }
+                PointerEvent::Button { button, pressed: true } => {
+                    if let Some((module, x, y)) = self.pointer_target() {
+                        module.borrow_mut().on_click(button, x, y);
+                        self.mark_changed(&[module]);
+                    }
+                }
+                PointerEvent::Button { pressed: false, .. } => {}
+                PointerEvent::Scroll { axis, delta } => {
+                    if let Some((module, _, _)) = self.pointer_target() {
+                        module.borrow_mut().on_scroll(axis, delta);
+                        self.mark_changed(&[module]);
+                    }
+                }
+            }
+
+            self.update_hover();
+        }
+    }
+
+    fn handle_touch_events(&mut self) {
+        for event in std::mem::take(&mut self.state.touch_events) {
+            match event {
+                TouchEvent::Down { serial, surface, id, x, y } => {
+                    let Some(slot) = self.bars
+                        .iter()
+                        .find(|bar| bar.base_surface == surface)
+                        .and_then(|bar| bar.module_at(x, y))
+                    else {
+                        continue;
+                    };
+
+                    self.touches.push(TouchPoint {
+                        id,
+                        serial,
+                        module: slot.module.clone(),
+                        slot: slot.rect,
+                        start: (x, y),
+                        position: (x, y),
+                        long_pressed: false,
+                    });
+
+                    self.add_timer(Instant::now() + input::LONG_PRESS_DELAY, move |client| {
+                        client.long_press(id, serial);
+                    });
+                }
+                TouchEvent::Motion { id, x, y } => {
+                    if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                        touch.position = (x, y);
+                    }
+                }
+                TouchEvent::Up { id } => {
+                    let Some(index) = self.touches.iter().position(|touch| touch.id == id) else {
+                        continue;
+                    };
+                    let touch = self.touches.remove(index);
+
+                    let (dx, dy) = touch.travel();
+                    let gesture = if touch.long_pressed {
+                        None
+                    } else if dx.abs() >= input::SWIPE_DISTANCE && dx.abs() > dy.abs() {
+                        Some(TouchGesture::Swipe { dx })
+                    } else if touch.is_stationary() {
+                        let (x, y) = touch.local_start();
+                        Some(TouchGesture::Tap { x, y })
+                    } else {
+                        None
+                    };
+
+                    if let Some(gesture) = gesture {
+                        touch.module.borrow_mut().on_touch(gesture);
+                        self.mark_changed(&[touch.module]);
+                    }
+                }
+                TouchEvent::Cancel => {
+                    self.touches.clear();
+                }
+            }
+        }
+    }
+
+    /// Fired by the timer set when a finger went down, a long press if it has not moved or lifted since.
+    fn long_press(&mut self, id: i32, serial: u32) {
+        let Some(touch) = self.touches
+            .iter_mut()
+            .find(|touch| touch.id == id && touch.serial == serial)
+            .filter(|touch| touch.is_stationary())
+        else {
+            return;
+        };
+
+        touch.long_pressed = true;
+
+        let (x, y) = touch.local_start();
+        let module = touch.module.clone();
+
+        module.borrow_mut().on_touch(TouchGesture::LongPress { x, y });
+        self.mark_changed(&[module]);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let timers = self.timers.iter().map(|timer| timer.deadline);
+        let modules = self.bars
+            .iter()
+            .flat_map(|bar| &bar.slots)
+            .filter_map(|slot| slot.module.borrow().next_update());
+
+        timers.chain(modules).min()
+    }
+
+    /// Blocks until a Wayland event, a watched source or the next timer is due, then dispatches it.
+    fn wait(&mut self) -> Result<()> {
+        self.connection.flush()?;
+
+        let Some(guard) = self.event_queue.prepare_read() else {
+            // Events are already queued, no need to block
+            self.event_queue.dispatch_pending(&mut self.state)?;
+            return Ok(());
+        };
+
+        let timeout = event_loop::timeout_until(self.next_deadline());
+        let (connection_readable, readable) = self.poll(Some(guard.connection_fd()), timeout)?;
+
+        if connection_readable {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            drop(guard);
+        }
+
+        self.event_queue.dispatch_pending(&mut self.state)?;
+        self.dispatch_sources(&readable);
+
+        Ok(())
+    }
+
+    /// Waits until the connection, if given, or a watched source becomes readable, or `timeout` passes.
+    /// Returns whether the connection is readable, and which sources are.
+    fn poll(&self, connection: Option<BorrowedFd<'_>>, timeout: PollTimeout) -> Result<(bool, Vec<bool>)> {
+        let mut fds: Vec<PollFd> = connection.map(|fd| PollFd::new(fd, PollFlags::POLLIN)).into_iter().collect();
+        fds.extend(self.sources.iter().map(|source| PollFd::new(source.fd(), PollFlags::POLLIN)));
+
+        let mut readable = match nix::poll::poll(&mut fds, timeout) {
+            Ok(_) => fds
+                .iter()
+                .map(|fd| fd.revents().is_some_and(|revents| !revents.is_empty()))
+                .collect(),
+            Err(nix::errno::Errno::EINTR) => vec![false; fds.len()],
+            Err(err) => return Err(Error::Poll(err)),
+        };
+
+        let connection_readable = connection.is_some() && readable.remove(0);
+        Ok((connection_readable, readable))
+    }
+
+    /// Calls the callbacks of the sources `poll` found readable.
+    fn dispatch_sources(&mut self, readable: &[bool]) {
+        let mut sources = std::mem::take(&mut self.sources);
+
+        let mut index = 0;
+        sources.retain_mut(|source| {
+            let keep = !readable[index] || source.ready(self);
+            index += 1;
+            keep
+        });
+
+        // Sources added by the callbacks were pushed onto the now empty list
+        sources.append(&mut self.sources);
+        self.sources = sources;
+    }
+
+    /// Drops everything tied to the lost connection and connects again once the compositor is back,
+    /// recreating the bars. Watched sources and timers keep running in the meantime.
+    fn reconnect(&mut self, timeout: Duration) -> Result<()> {
+        // The objects of the old connection are gone with it, only the modules have to be told.
+        // Without globals no bars are created until the new connection is up.
+        self.bars.clear();
+        self.state = State {
+            running: self.state.running,
+            ..State::default()
+        };
+        self.pointer = None;
+        self.touches.clear();
+        self.update_hover();
+
+        let give_up = Instant::now() + timeout;
+        loop {
+            match connect() {
+                Ok((connection, event_queue, state)) => {
+                    self.qh = event_queue.handle();
+                    self.state = State { running: self.state.running, ..state };
+                    self.connection = connection;
+                    self.event_queue = event_queue;
+
+                    self.closed.clear();
+                    self.sync_bars();
+                    return Ok(());
+                }
+                // A compositor still starting up may accept connections before it is ready for them,
+                // dropping them or not having announced all globals yet
+                Err(error)
+                    if Instant::now() < give_up
+                        && (matches!(error, Error::Connect(_) | Error::MissingGlobal { .. })
+                            || error.is_connection_lost()) => {}
+                Err(error) => return Err(error),
+            }
+
+            let retry = Instant::now() + RECONNECT_INTERVAL;
+            let deadline = self.next_deadline().map_or(retry, |deadline| deadline.min(retry));
+            let timeout = event_loop::timeout_until(Some(deadline));
+            let (_, readable) = self.poll(None, timeout)?;
+            self.dispatch_sources(&readable);
+            self.run_timers();
+
+            if !self.state.running {
+                return Ok(());
             }
         }
-    }    
+    }
 
-    pub fn start(&mut self) {
-        self.event_queue.blocking_dispatch(&mut self.state).unwrap();
-        self.render();
+    /// Makes `start` return after the current iteration of the event loop.
+    pub fn stop(&mut self) {
+        self.state.running = false;
+    }
 
+    /// Runs the event loop until `stop` is called, then takes the bars off the screen.
+    /// Fails if a buffer cannot be allocated, or if the connection to the compositor breaks
+    /// and cannot be established again within the reconnect timeout.
+    pub fn start(&mut self) -> Result<()> {
         while self.state.running {
-            self.event_queue.blocking_dispatch(&mut self.state).unwrap();
-            self.render();
-            std::thread::sleep(std::time::Duration::from_millis(1000)); // TODO: remove
+            match (self.run_once(), self.reconnect_timeout) {
+                (Ok(()), _) => {}
+                (Err(error), Some(timeout)) if error.is_connection_lost() => {
+                    self.report(&error);
+                    self.reconnect(timeout)?;
+                }
+                (Err(error), _) => return Err(error),
+            }
         }
+
+        // Unmap right away instead of waiting for the compositor to notice the connection is gone
+        for bar in &mut self.bars {
+            bar.destroy();
+        }
+
+        self.bars.clear();
+        self.state.layers.clear();
+
+        // Fails if stopped while waiting for the compositor, with nothing to unmap anyway
+        let _ = self.connection.flush();
+
+        Ok(())
+    }
+
+    fn run_once(&mut self) -> Result<()> {
+        if self.state.outputs_changed || self.state.layers.values().any(|layer| layer.closed) {
+            self.sync_bars();
+        }
+
+        self.run_timers();
+        self.handle_pointer_events();
+        self.handle_touch_events();
+        self.update_modules();
+        self.render()?;
+
+        self.wait()
     }
 }
+
+/// Connects to the compositor named by `WAYLAND_DISPLAY` and collects its globals and outputs.
+fn connect() -> Result<(wayland_client::Connection, wayland_client::EventQueue<State>, State)> {
+    let connection = wayland_client::Connection::connect_to_env()?;
+    let mut event_queue = connection.new_event_queue();
+    let qh = event_queue.handle();
+
+    let display = connection.display();
+    display.get_registry(&qh, ());
+
+    let mut state = State {
+        running: true,
+        ..State::default()
+    };
+
+    // The second roundtrip collects the initial wl_output and xdg-output events
+    event_queue.roundtrip(&mut state)?;
+    event_queue.roundtrip(&mut state)?;
+
+    let missing = |interface, protocol| Error::MissingGlobal { interface, protocol };
+    if state.compositor.is_none() {
+        return Err(missing("wl_compositor", "the core Wayland protocol"));
+    }
+    if state.shm.is_none() {
+        return Err(missing("wl_shm", "shared memory buffers"));
+    }
+    if state.layer_shell.is_none() {
+        return Err(missing("zwlr_layer_shell_v1", "wlr-layer-shell, which GNOME for one lacks"));
+    }
+
+    Ok((connection, event_queue, state))
+}
+
+/// Scale for surfaces drawn without fractional scaling: the highest of the outputs the
+/// surface is on, or of the output it was created for until the compositor tells.
+fn integer_scale(outputs: &[Output], entered: &[u32], fallback: Option<u32>) -> i32 {
+    let scales = outputs
+        .iter()
+        .filter(|output| entered.contains(&output.global_name))
+        .map(Output::scale)
+        .max();
+
+    scales
+        .or_else(|| fallback.and_then(|global_name| {
+            outputs.iter().find(|output| output.global_name == global_name).map(Output::scale)
+        }))
+        .unwrap_or(1)
+        .max(1)
+}
@@ -0,0 +1,712 @@
+use crate::bar::{BarConfig, BarLayer, BarOutput, BarPosition, DrawFn, ExclusiveZone, KeyboardInteractivity, MIN_BAR_SIZE};
+use crate::canvas::Canvas;
+use crate::layout::{Insets, ModuleStyle, VerticalAlign};
+use crate::modules::*;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chrono::format::{Item, StrftimeItems};
+use fontdue::{Font, FontSettings};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+/// Used when there is no config file, doubles as an example of the format.
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Tried in order when the config does not name a font.
+const FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/TTF/HackNerdFontMono-Regular.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu-sans-mono-fonts/DejaVuSansMono.ttf",
+    "/usr/share/fonts/noto/NotoSansMono-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansMono-Regular.ttf",
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, error: std::io::Error },
+    /// The message names the offending line and column
+    Parse { path: Option<PathBuf>, error: toml::de::Error },
+    Font { path: Option<PathBuf>, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "cannot read {}: {error}", path.display()),
+            ConfigError::Parse { path: Some(path), error } => write!(f, "in {}: {error}", path.display()),
+            ConfigError::Parse { path: None, error } => write!(f, "in the default config: {error}"),
+            ConfigError::Font { path: Some(path), message } => {
+                write!(f, "cannot load font {}: {message}", path.display())
+            }
+            ConfigError::Font { path: None, message } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A module the config gave a name or signal, so that it can be addressed from outside the bar.
+#[derive(Clone)]
+pub struct ModuleRef {
+    pub name: Option<String>,
+    /// Refreshes the module when `SIGRTMIN` plus this number is received
+    pub signal: Option<u32>,
+    pub module: ModuleHandle,
+}
+
+/// What a config turns into: the bars to show and the modules on them that can be addressed.
+pub struct Setup {
+    pub bars: Vec<(BarConfig, DrawFn)>,
+    pub modules: Vec<ModuleRef>,
+}
+
+/// `$XDG_CONFIG_HOME/ruwabar/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("ruwabar").join("config.toml"))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    /// Path to a TTF or OTF file
+    font: Option<PathBuf>,
+    #[serde(default, rename = "bar")]
+    bars: Vec<BarSection>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct BarSection {
+    position: BarPosition,
+    /// Height of horizontal bars, width of vertical ones
+    #[serde(deserialize_with = "bar_size")]
+    size: u32,
+    #[serde(default, deserialize_with = "output")]
+    output: Option<BarOutput>,
+    #[serde(default)]
+    layer: BarLayer,
+    #[serde(default, deserialize_with = "insets")]
+    margin: Insets,
+    #[serde(default, deserialize_with = "exclusive_zone")]
+    exclusive_zone: ExclusiveZone,
+    namespace: Option<String>,
+    #[serde(default)]
+    keyboard_interactivity: KeyboardInteractivity,
+    #[serde(default, deserialize_with = "color")]
+    background: u32,
+
+    #[serde(default)]
+    left: Vec<ModuleSection>,
+    #[serde(default)]
+    center: Vec<ModuleSection>,
+    #[serde(default)]
+    right: Vec<ModuleSection>,
+}
+
+/// A module table, holding both the module's own settings and how it is placed. The two are split
+/// apart while reading the table, as `deny_unknown_fields` does not work together with `flatten`.
+struct ModuleSection {
+    kind: ModuleKind,
+    placement: PlacementSection,
+}
+
+/// The keys of `PlacementSection`, all others belong to the module's type.
+const PLACEMENT_KEYS: &[&str] = &[
+    "name", "signal", "padding", "margin", "min-width", "max-width", "align", "grow", "priority",
+];
+
+/// The `type`s of `ModuleKind`.
+const MODULE_TYPES: &[&str] = &["spacing", "color", "toggle", "clock", "text"];
+
+/// Every key taken by at least one of the `ModuleKind`s.
+const MODULE_KEYS: &[&str] = &[
+    "type", "width", "color", "on-color", "off-color", "on", "format", "size", "vertical-text", "text",
+];
+
+impl<'de> Deserialize<'de> for ModuleSection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ModuleSectionVisitor)
+    }
+}
+
+struct ModuleSectionVisitor;
+
+impl<'de> de::Visitor<'de> for ModuleSectionVisitor {
+    type Value = ModuleSection;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a module table")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<ModuleSection, A::Error> {
+        let mut placement = toml::Table::new();
+        let mut kind = toml::Table::new();
+
+        while let Some(key) = map.next_key_seed(ModuleKey(kind.get("type")))? {
+            // Each value is checked while the deserializer still knows where it is written, as
+            // errors from the tables below can only point at the module's header
+            let value = map.next_value_seed(ModuleValue(&key))?;
+            match PLACEMENT_KEYS.contains(&key.as_str()) {
+                true => placement.insert(key, value),
+                false => kind.insert(key, value),
+            };
+        }
+
+        // Unknown keys end up with the type's settings, which alone would make a misleading list of keys
+        let kind = kind.try_into().map_err(|error: toml::de::Error| match error.message() {
+            message if message.starts_with("unknown field") => de::Error::custom(format!(
+                "{message}, or one of `{}`",
+                PLACEMENT_KEYS.join("`, `"),
+            )),
+            message => de::Error::custom(message),
+        })?;
+        let placement = placement.try_into().map_err(|error: toml::de::Error| de::Error::custom(error.message()))?;
+
+        Ok(ModuleSection { kind, placement })
+    }
+}
+
+/// Reads a key of a module table, rejecting those no module takes, and once the table's `type` is
+/// known, those the type does not take.
+struct ModuleKey<'a>(Option<&'a toml::Value>);
+
+impl<'de> de::DeserializeSeed<'de> for ModuleKey<'_> {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        let key = String::deserialize(deserializer)?;
+
+        if PLACEMENT_KEYS.contains(&key.as_str()) {
+            return Ok(key);
+        }
+        if !MODULE_KEYS.contains(&key.as_str()) {
+            return Err(de::Error::custom(format!(
+                "unknown field `{key}`, expected one of `{}`, or one of `{}`",
+                MODULE_KEYS.join("`, `"),
+                PLACEMENT_KEYS.join("`, `"),
+            )));
+        }
+
+        if let Some(kind) = self.0 {
+            // The value does not matter, `ModuleKind` rejects a key it does not take before looking at it
+            let table = toml::Table::from_iter([("type".to_string(), kind.clone()), (key.clone(), toml::Value::Boolean(false))]);
+            match ModuleKind::deserialize(toml::Value::Table(table)) {
+                Err(error) if error.message().starts_with("unknown field") => {
+                    return Err(de::Error::custom(format!(
+                        "{}, or one of `{}`",
+                        error.message(),
+                        PLACEMENT_KEYS.join("`, `"),
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+/// Reads the value of a key in a module table, checking it as the key's type would.
+struct ModuleValue<'a>(&'a str);
+
+impl<'de> de::DeserializeSeed<'de> for ModuleValue<'_> {
+    type Value = toml::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<toml::Value, D::Error> {
+        let ModuleValue(key) = self;
+        let value = toml::Value::deserialize(deserializer)?;
+
+        let copy = value.clone();
+        let checked = match key {
+            _ if PLACEMENT_KEYS.contains(&key) => {
+                PlacementSection::deserialize(toml::Value::Table(toml::Table::from_iter([(key.to_string(), copy)]))).map(drop)
+            }
+            "type" => match value.as_str() {
+                Some(name) if !MODULE_TYPES.contains(&name) => Err(de::Error::unknown_variant(name, MODULE_TYPES)),
+                _ => String::deserialize(copy).map(drop),
+            },
+            "width" => u32::deserialize(copy).map(drop),
+            "color" | "on-color" | "off-color" => color(copy).map(drop),
+            "on" => bool::deserialize(copy).map(drop),
+            "format" => clock_format(copy).map(drop),
+            "size" => f32::deserialize(copy).map(drop),
+            "vertical-text" => VerticalText::deserialize(copy).map(drop),
+            "text" => String::deserialize(copy).map(drop),
+            _ => unreachable!("`ModuleKey` lets only known keys through"),
+        };
+
+        checked.map_err(|error: toml::de::Error| de::Error::custom(error.message()))?;
+        Ok(value)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PlacementSection {
+    /// Lets `ruwabar msg` refer to the module
+    name: Option<String>,
+    #[serde(default, deserialize_with = "signal")]
+    signal: Option<u32>,
+
+    #[serde(default, deserialize_with = "insets")]
+    padding: Insets,
+    #[serde(default, deserialize_with = "insets")]
+    margin: Insets,
+    min_width: Option<u32>,
+    max_width: Option<u32>,
+    #[serde(default)]
+    align: VerticalAlign,
+    #[serde(default)]
+    grow: u32,
+    #[serde(default)]
+    priority: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields, rename_all = "kebab-case", rename_all_fields = "kebab-case")]
+enum ModuleKind {
+    Spacing {
+        width: u32,
+    },
+    Color {
+        width: u32,
+        #[serde(deserialize_with = "color")]
+        color: u32,
+    },
+    Toggle {
+        width: u32,
+        #[serde(deserialize_with = "color")]
+        on_color: u32,
+        #[serde(deserialize_with = "color")]
+        off_color: u32,
+        #[serde(default)]
+        on: bool,
+    },
+    Clock {
+        #[serde(default = "default_clock_format", deserialize_with = "clock_format")]
+        format: String,
+        #[serde(default = "default_text_color", deserialize_with = "color")]
+        color: u32,
+        #[serde(default = "default_font_size")]
+        size: f32,
+        #[serde(default)]
+        vertical_text: VerticalText,
+    },
+    Text {
+        #[serde(default)]
+        text: String,
+        #[serde(default = "default_text_color", deserialize_with = "color")]
+        color: u32,
+        #[serde(default = "default_font_size")]
+        size: f32,
+        #[serde(default)]
+        vertical_text: VerticalText,
+    },
+}
+
+fn default_clock_format() -> String {
+    "%H:%M".to_string()
+}
+
+fn default_text_color() -> u32 {
+    0xFF000000
+}
+
+fn default_font_size() -> f32 {
+    16.0
+}
+
+impl Config {
+    pub(crate) fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        Self::parse(&text, Some(path))
+    }
+
+    /// Parses a config, `path` is where it came from and is only used in messages.
+    pub(crate) fn parse(text: &str, path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config: Config = toml::from_str(text).map_err(|error| ConfigError::Parse {
+            path: path.map(Path::to_path_buf),
+            error,
+        })?;
+
+        config.path = path.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// Instantiates the modules of every bar, returning the bars to add to the client.
+    /// `indicator` goes at the end of every bar, to report problems with later configs.
+    pub(crate) fn setup(&self, indicator: &ModuleHandle) -> Result<Setup, ConfigError> {
+        let font = self.load_font()?;
+        let mut refs = Vec::new();
+
+        let bars = self.bars
+            .iter()
+            .map(|section| {
+                let mut bar = BarConfig::new(section.position, section.size)
+                    .layer(section.layer)
+                    .margin(section.margin)
+                    .exclusive_zone(section.exclusive_zone)
+                    .keyboard_interactivity(section.keyboard_interactivity);
+
+                if let Some(output) = &section.output {
+                    bar = bar.output(output.clone());
+                }
+
+                if let Some(namespace) = &section.namespace {
+                    bar = bar.namespace(namespace.clone());
+                }
+
+                let left = build_modules(&section.left, &font, &mut refs);
+                let center = build_modules(&section.center, &font, &mut refs);
+                let right = build_modules(&section.right, &font, &mut refs).add_shared(
+                    indicator.clone(),
+                    ModuleStyle {
+                        align: VerticalAlign::Center,
+                        priority: i32::MAX,
+                        ..ModuleStyle::default()
+                    },
+                );
+                let background = section.background;
+
+                let draw: DrawFn = Rc::new(move |canvas: &mut Canvas| {
+                    canvas.fill(background);
+                    canvas.draw_module_groups(&left, &center, &right);
+                });
+
+                (bar, draw)
+            })
+            .collect();
+
+        Ok(Setup { bars, modules: refs })
+    }
+
+    fn load_font(&self) -> Result<Rc<Font>, ConfigError> {
+        let path = match &self.font {
+            // Relative to the config file, like most configs do
+            Some(font) => match self.path.as_deref().and_then(Path::parent) {
+                Some(dir) => dir.join(font),
+                None => font.clone(),
+            },
+            None => FONT_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .ok_or_else(|| ConfigError::Font {
+                    path: None,
+                    message: format!("none of the default fonts is installed, set `font` to the path of a TTF file (tried {})", FONT_PATHS.join(", ")),
+                })?,
+        };
+
+        let bytes = std::fs::read(&path).map_err(|error| ConfigError::Font {
+            path: Some(path.clone()),
+            message: error.to_string(),
+        })?;
+
+        let font = Font::from_bytes(bytes, FontSettings::default()).map_err(|message| ConfigError::Font {
+            path: Some(path),
+            message: message.to_string(),
+        })?;
+
+        Ok(Rc::new(font))
+    }
+}
+
+fn build_modules(sections: &[ModuleSection], font: &Rc<Font>, refs: &mut Vec<ModuleRef>) -> Modules {
+    sections.iter().fold(Modules::new(), |modules, section| {
+        let placement = &section.placement;
+        let style = ModuleStyle {
+            padding: placement.padding,
+            margin: placement.margin,
+            min_width: placement.min_width,
+            max_width: placement.max_width,
+            align: placement.align,
+            grow: placement.grow,
+            priority: placement.priority,
+        };
+
+        let module: ModuleHandle = match &section.kind {
+            ModuleKind::Spacing { width } => Rc::new(RefCell::new(SpacingModule { width: *width })),
+            ModuleKind::Color { width, color } => {
+                Rc::new(RefCell::new(ColorModule { width: *width, color: *color }))
+            }
+            ModuleKind::Toggle { width, on_color, off_color, on } => Rc::new(RefCell::new(
+                ToggleModule { width: *width, on_color: *on_color, off_color: *off_color, on: *on },
+            )),
+            ModuleKind::Clock { format, color, size, vertical_text } => Rc::new(RefCell::new(
+                ClockModule::new(format, *color, font.clone(), *size).vertical_text(*vertical_text),
+            )),
+            ModuleKind::Text { text, color, size, vertical_text } => Rc::new(RefCell::new(
+                TextModule::new(text, *color, font.clone(), *size).vertical_text(*vertical_text),
+            )),
+        };
+
+        if placement.name.is_some() || placement.signal.is_some() {
+            refs.push(ModuleRef {
+                name: placement.name.clone(),
+                signal: placement.signal,
+                module: module.clone(),
+            });
+        }
+
+        modules.add_shared(module, style)
+    })
+}
+
+/// `"#RRGGBB"` or `"#AARRGGBB"`.
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let invalid = || de::Error::custom(format!("invalid color `{text}`, expected #RRGGBB or #AARRGGBB"));
+
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+    let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+
+    match hex.len() {
+        6 => Ok(0xFF000000 | value),
+        8 => Ok(value),
+        _ => Err(invalid()),
+    }
+}
+
+/// At least `MIN_BAR_SIZE`, which `BarConfig` would quietly clamp to.
+fn bar_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match u32::deserialize(deserializer)? {
+        size if size < MIN_BAR_SIZE => Err(de::Error::invalid_value(
+            de::Unexpected::Unsigned(size.into()),
+            &format!("a size of at least {MIN_BAR_SIZE} pixel").as_str(),
+        )),
+        size => Ok(size),
+    }
+}
+
+/// A strftime format chrono understands, which would otherwise panic when the clock formats the time.
+fn clock_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let format = String::deserialize(deserializer)?;
+
+    if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error)) {
+        return Err(de::Error::custom(format!("invalid clock format `{format}`, see chrono's strftime specifiers")));
+    }
+
+    Ok(format)
+}
+
+/// Like CSS: one value for all sides, `[vertical, horizontal]`, `[top, right, bottom, left]`,
+/// or a table naming the sides.
+fn insets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Insets, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        All(u32),
+        List(Vec<u32>),
+        Sides {
+            #[serde(default)]
+            top: u32,
+            #[serde(default)]
+            right: u32,
+            #[serde(default)]
+            bottom: u32,
+            #[serde(default)]
+            left: u32,
+        },
+    }
+
+    let value = Value::deserialize(deserializer).map_err(|_| {
+        de::Error::custom("expected a number, a list of 2 or 4 numbers, or a table of top, right, bottom and left")
+    })?;
+
+    match value {
+        Value::All(value) => Ok(Insets::all(value)),
+        Value::List(list) => match list[..] {
+            [vertical, horizontal] => Ok(Insets::symmetric(vertical, horizontal)),
+            [top, right, bottom, left] => Ok(Insets { top, right, bottom, left }),
+            _ => Err(de::Error::invalid_length(list.len(), &"2 or 4 numbers")),
+        },
+        Value::Sides { top, right, bottom, left } => Ok(Insets { top, right, bottom, left }),
+    }
+}
+
+/// Offset from `SIGRTMIN`, which has to stay below `SIGRTMAX`.
+fn signal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let signal = u32::deserialize(deserializer)?;
+    let max = (libc::SIGRTMAX() - libc::SIGRTMIN()) as u32;
+
+    if signal > max {
+        return Err(de::Error::custom(format!("signal {signal} is past SIGRTMAX, expected at most {max}")));
+    }
+
+    Ok(Some(signal))
+}
+
+/// `"all"`, `"focused"` or the name of an output.
+fn output<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BarOutput>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    Ok(Some(match name.as_str() {
+        "all" => BarOutput::All,
+        "focused" => BarOutput::Focused,
+        _ => BarOutput::Name(name),
+    }))
+}
+
+/// `"auto"`, `"none"` or a distance in pixels.
+fn exclusive_zone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ExclusiveZone, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Custom(u32),
+        Keyword(String),
+    }
+
+    match Value::deserialize(deserializer)? {
+        Value::Custom(zone) => Ok(ExclusiveZone::Custom(zone)),
+        Value::Keyword(keyword) if keyword == "auto" => Ok(ExclusiveZone::Auto),
+        Value::Keyword(keyword) if keyword == "none" => Ok(ExclusiveZone::None),
+        Value::Keyword(keyword) => Err(de::Error::invalid_value(
+            de::Unexpected::Str(&keyword),
+            &"\"auto\", \"none\" or a number of pixels",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(settings: &str) -> Result<BarSection, ConfigError> {
+        let text = format!("[[bar]]\nposition = \"top\"\nsize = 20\n{settings}\n");
+        Ok(Config::parse(&text, None)?.bars.remove(0))
+    }
+
+    fn module(settings: &str) -> Result<ModuleSection, ConfigError> {
+        Ok(bar(&format!("[[bar.left]]\n{settings}"))?.left.remove(0))
+    }
+
+    /// The message, which starts by naming the line of the error.
+    fn error<T>(result: Result<T, ConfigError>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn default_config_parses() {
+        Config::parse(DEFAULT_CONFIG, None).unwrap();
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(bar("background = \"#102030\"").unwrap().background, 0xFF102030);
+        assert_eq!(bar("background = \"#80102030\"").unwrap().background, 0x80102030);
+        assert_eq!(bar("").unwrap().background, 0);
+
+        for color in ["\"102030\"", "\"#1020\"", "\"#10203g\"", "\"red\"", "0"] {
+            let message = error(bar(&format!("background = {color}")));
+            assert!(message.contains("line 4"), "{message}");
+        }
+    }
+
+    #[test]
+    fn insets() {
+        assert_eq!(bar("margin = 3").unwrap().margin, Insets::all(3));
+        assert_eq!(bar("margin = [1, 2]").unwrap().margin, Insets::symmetric(1, 2));
+        assert_eq!(bar("margin = [1, 2, 3, 4]").unwrap().margin, Insets { top: 1, right: 2, bottom: 3, left: 4 });
+        assert_eq!(bar("margin = { left = 4 }").unwrap().margin, Insets { left: 4, ..Insets::default() });
+
+        assert!(error(bar("margin = [1, 2, 3]")).contains("2 or 4 numbers"));
+        assert!(error(bar("margin = \"wide\"")).contains("expected a number"));
+    }
+
+    #[test]
+    fn bar_size() {
+        assert_eq!(bar("").unwrap().size, 20);
+
+        let text = "[[bar]]\nposition = \"top\"\nsize = 0\n";
+        let message = error(Config::parse(text, None));
+        assert!(message.contains("line 3") && message.contains("at least 1 pixel"), "{message}");
+    }
+
+    #[test]
+    fn exclusive_zone() {
+        assert_eq!(bar("").unwrap().exclusive_zone, ExclusiveZone::Auto);
+        assert_eq!(bar("exclusive-zone = \"auto\"").unwrap().exclusive_zone, ExclusiveZone::Auto);
+        assert_eq!(bar("exclusive-zone = \"none\"").unwrap().exclusive_zone, ExclusiveZone::None);
+        assert_eq!(bar("exclusive-zone = 12").unwrap().exclusive_zone, ExclusiveZone::Custom(12));
+
+        assert!(error(bar("exclusive-zone = \"some\"")).contains("\"auto\", \"none\" or a number of pixels"));
+    }
+
+    #[test]
+    fn output() {
+        assert_eq!(bar("").unwrap().output, None);
+        assert_eq!(bar("output = \"all\"").unwrap().output, Some(BarOutput::All));
+        assert_eq!(bar("output = \"focused\"").unwrap().output, Some(BarOutput::Focused));
+        assert_eq!(bar("output = \"DP-1\"").unwrap().output, Some(BarOutput::Name("DP-1".to_string())));
+
+        assert!(error(bar("output = 1")).contains("line 4"));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let message = error(bar("heigth = 20"));
+        assert!(message.contains("line 4") && message.contains("unknown field `heigth`"), "{message}");
+
+        let message = error(Config::parse("fnot = \"a.ttf\"\n", None));
+        assert!(message.contains("line 1") && message.contains("unknown field `fnot`"), "{message}");
+    }
+
+    #[test]
+    fn modules() {
+        let section = module("type = \"toggle\"\nwidth = 8\non-color = \"#00FF00\"\noff-color = \"#FF0000\"\nname = \"mic\"\npadding = [1, 2]\nsignal = 3").unwrap();
+        assert!(matches!(section.kind, ModuleKind::Toggle { width: 8, on_color: 0xFF00FF00, off_color: 0xFFFF0000, on: false }));
+        assert_eq!(section.placement.name.as_deref(), Some("mic"));
+        assert_eq!(section.placement.padding, Insets::symmetric(1, 2));
+        assert_eq!(section.placement.signal, Some(3));
+
+        // Placement keys may come before the type
+        let section = module("priority = 2\ntype = \"clock\"\nformat = \"%a %H:%M\"").unwrap();
+        assert!(matches!(section.kind, ModuleKind::Clock { ref format, color: 0xFF000000, .. } if format == "%a %H:%M"));
+        assert_eq!(section.placement.priority, 2);
+    }
+
+    /// Errors in a module table point at the key or value at fault, the table starts on line 4.
+    #[test]
+    fn module_errors() {
+        let cases = [
+            ("type = \"clok\"", "line 5", "unknown variant `clok`"),
+            ("type = \"clock\"\ncolr = \"#FFFFFF\"", "line 6", "unknown field `colr`"),
+            ("type = \"clock\"\npriorty = 1", "line 6", "or one of `name`"),
+            ("type = \"clock\"\nwidth = 10", "line 6", "unknown field `width`, expected one of `format`"),
+            ("type = \"clock\"\nformat = \"%Q\"", "line 6", "invalid clock format `%Q`"),
+            ("type = \"clock\"\ncolor = \"blue\"", "line 6", "invalid color `blue`"),
+            ("type = \"clock\"\npadding = [1, 2, 3]", "line 6", "2 or 4 numbers"),
+            ("type = \"clock\"\nsignal = 1000", "line 6", "past SIGRTMAX"),
+            ("type = \"spacing\"\nwidth = -1", "line 6", "width"),
+        ];
+
+        for (settings, line, expected) in cases {
+            let message = error(module(settings));
+            assert!(message.contains(line) && message.contains(expected), "{settings}: {message}");
+        }
+
+        // Only known once the whole table is read
+        let message = error(module("type = \"color\"\nwidth = 10"));
+        assert!(message.contains("missing field `color`"), "{message}");
+    }
+}
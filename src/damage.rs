@@ -0,0 +1,160 @@
+use crate::canvas::Rect;
+
+/// Side of the square tiles damage is tracked in, in pixels.
+pub(crate) const TILE_SIZE: u32 = 32;
+
+/// Rectangle lists longer than this are collapsed into their bounding box.
+const MAX_RECTS: usize = 16;
+
+/// The tiles of a surface whose pixels changed during a frame.
+#[derive(Default)]
+pub struct Damage {
+    width: u32,
+    height: u32,
+    columns: u32,
+    tiles: Vec<bool>,
+}
+
+impl Damage {
+    /// Forgets all damage, resizing the tile grid if the surface size changed.
+    pub(crate) fn reset(&mut self, width: u32, height: u32) {
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            self.columns = width.div_ceil(TILE_SIZE);
+            self.tiles = vec![false; (self.columns * height.div_ceil(TILE_SIZE)) as usize];
+        } else {
+            self.tiles.fill(false);
+        }
+    }
+
+    pub(crate) fn mark(&mut self, x: u32, y: u32) {
+        let index = (x / TILE_SIZE + y / TILE_SIZE * self.columns) as usize;
+        if let Some(tile) = self.tiles.get_mut(index) {
+            *tile = true;
+        }
+    }
+
+    /// Unmarks the tiles whose pixels in `after` are the same as in `before`, both buffers being
+    /// `stride` pixels wide. Drawing marks every tile it writes to, even if a later write puts the
+    /// old pixels back, as drawing a background and then the modules over it does.
+    pub(crate) fn retain_changed(&mut self, before: &[u32], after: &[u32], stride: u32) {
+        let columns = self.columns.max(1);
+        let (width, height) = (self.width, self.height);
+
+        for (index, tile) in self.tiles.iter_mut().enumerate().filter(|(_, tile)| **tile) {
+            let x = index as u32 % columns * TILE_SIZE;
+            let y = index as u32 / columns * TILE_SIZE;
+            let tile_width = TILE_SIZE.min(width - x) as usize;
+
+            *tile = (y..(y + TILE_SIZE).min(height)).any(|row| {
+                let start = (row * stride + x) as usize;
+                before[start..start + tile_width] != after[start..start + tile_width]
+            });
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.tiles.contains(&true)
+    }
+
+    /// The damaged tiles as rectangles, horizontal runs of tiles merged with identical runs below them.
+    pub(crate) fn rects(&self) -> Vec<Rect> {
+        let mut rects: Vec<Rect> = Vec::new();
+
+        for (row, tiles) in self.tiles.chunks(self.columns.max(1) as usize).enumerate() {
+            let y = row as u32 * TILE_SIZE;
+            let height = TILE_SIZE.min(self.height - y);
+
+            let mut column = 0;
+            while column < tiles.len() {
+                if !tiles[column] {
+                    column += 1;
+                    continue;
+                }
+
+                let start = column;
+                while column < tiles.len() && tiles[column] {
+                    column += 1;
+                }
+
+                let x = start as u32 * TILE_SIZE;
+                let width = (column as u32 * TILE_SIZE).min(self.width) - x;
+
+                let above = rects.iter_mut().find(|rect| {
+                    rect.x == x as i32 && rect.width == width && rect.y + rect.height as i32 == y as i32
+                });
+
+                match above {
+                    Some(rect) => rect.height += height,
+                    None => rects.push(Rect::new(x as i32, y as i32, width, height)),
+                }
+            }
+        }
+
+        rects
+    }
+}
+
+/// Adds `rect` to a damage list, keeping the list short.
+pub(crate) fn add_rect(rects: &mut Vec<Rect>, rect: Rect) {
+    rects.push(rect);
+
+    if rects.len() > MAX_RECTS {
+        let bounds = rects.iter().fold(rects[0], |bounds, rect| bounds.union(rect));
+        rects.clear();
+        rects.push(bounds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::{Canvas, Frame};
+    use crate::layout::Orientation;
+
+    const WIDTH: u32 = 80;
+    const HEIGHT: u32 = 40;
+
+    /// Draws a frame like a bar does into `pixels`, returning the damage left after comparing with `front`.
+    fn redraw(pixels: &mut [u32], front: &[u32], color: u32) -> Vec<Rect> {
+        let mut frame = Frame::default();
+        let mut canvas = Canvas::new(pixels, WIDTH, HEIGHT, 1.0, Orientation::Horizontal, &mut frame);
+        canvas.fill(0x80202020);
+        canvas.fill_rect(40.0, 4.0, 10.0, 10.0, color);
+
+        frame.damage.retain_changed(front, pixels, WIDTH);
+        frame.damage.rects()
+    }
+
+    #[test]
+    fn identical_redraw_leaves_no_damage() {
+        let blank = vec![0; (WIDTH * HEIGHT) as usize];
+        let mut front = blank.clone();
+        redraw(&mut front, &blank, 0xFFFFFFFF);
+
+        let mut back = front.clone();
+        assert_eq!(redraw(&mut back, &front, 0xFFFFFFFF), vec![]);
+    }
+
+    #[test]
+    fn redraw_damages_changed_tiles_only() {
+        let blank = vec![0; (WIDTH * HEIGHT) as usize];
+        let mut front = blank.clone();
+        redraw(&mut front, &blank, 0xFFFFFFFF);
+
+        let mut back = front.clone();
+        assert_eq!(redraw(&mut back, &front, 0xFF000000), vec![Rect::new(32, 0, 32, 32)]);
+    }
+
+    #[test]
+    fn rects_merge_runs_and_stop_at_edges() {
+        let mut damage = Damage::default();
+        damage.reset(WIDTH, HEIGHT);
+        for (x, y) in [(0, 0), (40, 0), (70, 0), (0, 35), (40, 35), (70, 35)] {
+            damage.mark(x, y);
+        }
+
+        assert_eq!(damage.rects(), vec![Rect::new(0, 0, WIDTH, HEIGHT)]);
+    }
+}
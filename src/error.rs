@@ -0,0 +1,94 @@
+use crate::config::ConfigError;
+
+use std::fmt;
+
+use wayland_client::backend::WaylandError;
+use wayland_client::{ConnectError, DispatchError};
+
+#[derive(Debug)]
+pub enum Error {
+    /// No compositor could be reached, usually because `WAYLAND_DISPLAY` is not set
+    Connect(ConnectError),
+    /// The compositor does not implement a protocol the bar cannot do without
+    MissingGlobal {
+        interface: &'static str,
+        protocol: &'static str,
+    },
+    /// The connection broke while talking to the compositor
+    Connection(WaylandError),
+    /// The compositor sent something the bar could not handle
+    Dispatch(DispatchError),
+    /// Creating or mapping the shared memory of a buffer failed
+    Shm(std::io::Error),
+    Poll(nix::errno::Errno),
+    Config(ConfigError),
+}
+
+impl Error {
+    /// Whether the compositor went away, as opposed to rejecting something the client did.
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(
+            self,
+            Error::Connection(WaylandError::Io(_)) | Error::Dispatch(DispatchError::Backend(WaylandError::Io(_)))
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connect(error) => {
+                write!(f, "cannot connect to the Wayland compositor ({error}), is WAYLAND_DISPLAY set?")
+            }
+            Error::MissingGlobal { interface, protocol } => write!(
+                f,
+                "the compositor does not support {protocol} ({interface}), a compositor implementing it is needed",
+            ),
+            Error::Connection(error) => write!(f, "lost the connection to the compositor: {error}"),
+            Error::Dispatch(error) => write!(f, "error while handling a compositor event: {error}"),
+            Error::Shm(error) => write!(f, "cannot allocate shared memory for a buffer: {error}"),
+            Error::Poll(error) => write!(f, "cannot wait for events: {error}"),
+            Error::Config(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connect(error) => Some(error),
+            Error::MissingGlobal { .. } => None,
+            Error::Connection(error) => Some(error),
+            Error::Dispatch(error) => Some(error),
+            Error::Shm(error) => Some(error),
+            Error::Poll(error) => Some(error),
+            Error::Config(error) => Some(error),
+        }
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(error: ConnectError) -> Self {
+        Error::Connect(error)
+    }
+}
+
+impl From<WaylandError> for Error {
+    fn from(error: WaylandError) -> Self {
+        Error::Connection(error)
+    }
+}
+
+impl From<DispatchError> for Error {
+    fn from(error: DispatchError) -> Self {
+        Error::Dispatch(error)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::Config(error)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,49 @@
+use crate::client::Client;
+
+use std::os::fd::{AsFd, BorrowedFd};
+use std::time::Instant;
+
+use nix::poll::PollTimeout;
+
+/// A file descriptor watched by the client's event loop.
+pub(crate) trait EventSource {
+    fn fd(&self) -> BorrowedFd<'_>;
+
+    /// Called once the descriptor is readable, returns whether to keep watching it.
+    fn ready(&mut self, client: &mut Client) -> bool;
+}
+
+pub(crate) struct FdSource<S, F> {
+    pub(crate) source: S,
+    pub(crate) callback: F,
+}
+
+impl<S, F> EventSource for FdSource<S, F>
+where
+    S: AsFd,
+    F: FnMut(&mut Client, &mut S) -> bool,
+{
+    fn fd(&self) -> BorrowedFd<'_> {
+        self.source.as_fd()
+    }
+
+    fn ready(&mut self, client: &mut Client) -> bool {
+        (self.callback)(client, &mut self.source)
+    }
+}
+
+pub(crate) struct Timer {
+    pub(crate) deadline: Instant,
+    pub(crate) callback: Box<dyn FnOnce(&mut Client)>,
+}
+
+/// Poll timeout until `deadline`, rounded up to whole milliseconds so that we never wake up before it.
+pub(crate) fn timeout_until(deadline: Option<Instant>) -> PollTimeout {
+    match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            PollTimeout::try_from(remaining.as_micros().div_ceil(1000)).unwrap_or(PollTimeout::MAX)
+        }
+        None => PollTimeout::NONE,
+    }
+}
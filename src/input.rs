@@ -0,0 +1,200 @@
+use crate::state::State;
+
+use std::time::Duration;
+
+use wayland_client::{
+    protocol::*,
+    Proxy,
+    WEnum,
+};
+
+/// How long a finger has to rest on a module for a long press.
+pub(crate) const LONG_PRESS_DELAY: Duration = Duration::from_millis(500);
+/// How far a finger may wander during a tap or long press, in pixels.
+pub(crate) const TAP_SLOP: f64 = 10.0;
+/// How far a finger has to travel horizontally for a swipe, in pixels.
+pub(crate) const SWIPE_DISTANCE: f64 = 40.0;
+
+pub(crate) struct Seat {
+    pub(crate) global_name: u32,
+    pub(crate) wl_seat: wl_seat::WlSeat,
+    pub(crate) pointer: Option<wl_pointer::WlPointer>,
+    pub(crate) touch: Option<wl_touch::WlTouch>,
+}
+
+impl Seat {
+    pub(crate) fn new(global_name: u32, wl_seat: wl_seat::WlSeat) -> Self {
+        Self {
+            global_name,
+            wl_seat,
+            pointer: None,
+            touch: None,
+        }
+    }
+
+    pub(crate) fn destroy(&mut self) {
+        if let Some(pointer) = self.pointer.take() {
+            release_pointer(pointer);
+        }
+
+        if let Some(touch) = self.touch.take() {
+            release_touch(touch);
+        }
+
+        if self.wl_seat.version() >= 5 {
+            self.wl_seat.release();
+        }
+    }
+}
+
+fn release_pointer(pointer: wl_pointer::WlPointer) {
+    if pointer.version() >= 3 {
+        pointer.release();
+    }
+}
+
+fn release_touch(touch: wl_touch::WlTouch) {
+    if touch.version() >= 3 {
+        touch.release();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// Pointer events queued by the dispatcher, coordinates are surface-local.
+pub(crate) enum PointerEvent {
+    Enter { surface: wl_surface::WlSurface, x: f64, y: f64 },
+    Leave,
+    Motion { x: f64, y: f64 },
+    Button { button: u32, pressed: bool },
+    Scroll { axis: ScrollAxis, delta: f64 },
+}
+
+/// Touch events queued by the dispatcher, `id` tells apart the fingers currently down.
+pub(crate) enum TouchEvent {
+    Down { serial: u32, surface: wl_surface::WlSurface, id: i32, x: f64, y: f64 },
+    Up { id: i32 },
+    Motion { id: i32, x: f64, y: f64 },
+    Cancel,
+}
+
+/// What a finger did on a module, coordinates are relative to the module's slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TouchGesture {
+    Tap { x: f64, y: f64 },
+    LongPress { x: f64, y: f64 },
+    /// A mostly horizontal swipe, positive distances going right
+    Swipe { dx: f64 },
+}
+
+impl wayland_client::Dispatch<wl_seat::WlSeat, u32> for State {
+    fn event(
+        state: &mut Self,
+        wl_seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        global_name: &u32,
+        _: &wayland_client::Connection,
+        qh: &wayland_client::QueueHandle<Self>,
+    ) {
+        let Some(seat) = state.seats.iter_mut().find(|seat| seat.global_name == *global_name) else {
+            return;
+        };
+
+        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(capabilities) } = event {
+            let has_pointer = capabilities.contains(wl_seat::Capability::Pointer);
+
+            if has_pointer && seat.pointer.is_none() {
+                seat.pointer = Some(wl_seat.get_pointer(qh, ()));
+            } else if !has_pointer {
+                if let Some(pointer) = seat.pointer.take() {
+                    release_pointer(pointer);
+                }
+            }
+
+            let has_touch = capabilities.contains(wl_seat::Capability::Touch);
+
+            if has_touch && seat.touch.is_none() {
+                seat.touch = Some(wl_seat.get_touch(qh, ()));
+            } else if !has_touch {
+                if let Some(touch) = seat.touch.take() {
+                    release_touch(touch);
+                }
+            }
+        }
+    }
+}
+
+impl wayland_client::Dispatch<wl_pointer::WlPointer, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        let event = match event {
+            wl_pointer::Event::Enter { surface, surface_x, surface_y, .. } => {
+                PointerEvent::Enter { surface, x: surface_x, y: surface_y }
+            }
+            wl_pointer::Event::Leave { .. } => PointerEvent::Leave,
+            wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+                PointerEvent::Motion { x: surface_x, y: surface_y }
+            }
+            wl_pointer::Event::Button { button, state: button_state, .. } => {
+                PointerEvent::Button {
+                    button,
+                    pressed: button_state == WEnum::Value(wl_pointer::ButtonState::Pressed),
+                }
+            }
+            wl_pointer::Event::Axis { axis, value, .. } => {
+                let axis = match axis {
+                    WEnum::Value(wl_pointer::Axis::HorizontalScroll) => ScrollAxis::Horizontal,
+                    _ => ScrollAxis::Vertical,
+                };
+
+                PointerEvent::Scroll { axis, delta: value }
+            }
+            _ => return,
+        };
+
+        state.pointer_events.push(event);
+    }
+}
+
+impl wayland_client::Dispatch<wl_touch::WlTouch, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_touch::WlTouch,
+        event: wl_touch::Event,
+        _: &(),
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        let event = match event {
+            wl_touch::Event::Down { serial, surface, id, x, y, .. } => {
+                TouchEvent::Down { serial, surface, id, x, y }
+            }
+            wl_touch::Event::Up { id, .. } => TouchEvent::Up { id },
+            wl_touch::Event::Motion { id, x, y, .. } => TouchEvent::Motion { id, x, y },
+            wl_touch::Event::Cancel => TouchEvent::Cancel,
+            _ => return,
+        };
+
+        state.touch_events.push(event);
+    }
+}
+
+/// Releases the seat's objects when the global disappears, returning whether it was a seat.
+pub(crate) fn remove_seat(state: &mut State, global_name: u32) -> bool {
+    let Some(index) = state.seats.iter().position(|seat| seat.global_name == global_name) else {
+        return false;
+    };
+
+    state.seats.remove(index).destroy();
+    true
+}
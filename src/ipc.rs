@@ -0,0 +1,185 @@
+use crate::client::Client;
+use crate::modules::ModuleHandle;
+use crate::reload::ConfigSource;
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Commands understood on the control socket, one per line. Each is answered with a line
+/// reading `ok` or `error: ` followed by what went wrong.
+pub const USAGE: &str = "\
+commands:
+  show [namespace]      show the bars with the namespace, or all bars
+  hide [namespace]      hide them, freeing the space they reserve
+  toggle [namespace]    show them if hidden, hide them otherwise
+  reload                reload the config file
+  refresh [module]      update the modules with the name, or all modules
+  set <module> <value>  push a value into the modules with the name, e.g. a text module's text";
+
+/// `$XDG_RUNTIME_DIR/ruwabar-<display>.sock`, one per Wayland display.
+pub fn socket_path() -> Option<PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    let display = Path::new(&display).file_name()?.to_string_lossy().into_owned();
+
+    Some(PathBuf::from(runtime_dir).join(format!("ruwabar-{display}.sock")))
+}
+
+/// The listening socket, removed again when the event loop drops it.
+struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Answers commands sent to the control socket from the client's event loop.
+pub fn listen(client: &mut Client, source: ConfigSource) -> io::Result<()> {
+    let path = socket_path().ok_or_else(|| io::Error::new(ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+
+    // A socket nobody listens on is left over from a crashed instance
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("another instance is listening on {}", path.display()),
+        ));
+    }
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+
+    client.add_source(Listener { listener, path }, move |client, listener| {
+        while let Ok((stream, _)) = listener.listener.accept() {
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+
+            let source = source.clone();
+            let mut buffer = Vec::new();
+            client.add_source(stream, move |client, stream| serve(client, &source, stream, &mut buffer));
+        }
+
+        true
+    });
+
+    Ok(())
+}
+
+/// Longest command accepted, so that a client cannot make the bar buffer without end.
+const MAX_LINE: usize = 4096;
+
+/// Reads what arrived on a connection, answering every complete line. Returns whether to keep the connection.
+fn serve(client: &mut Client, source: &ConfigSource, stream: &mut UnixStream, buffer: &mut Vec<u8>) -> bool {
+    let mut chunk = [0; 1024];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => return false,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return err.kind() == ErrorKind::WouldBlock,
+        }
+
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+
+            let reply = match handle(client, source, &String::from_utf8_lossy(&line)) {
+                Ok(()) => "ok\n".to_string(),
+                Err(message) => format!("error: {}\n", message.replace('\n', " ")),
+            };
+
+            if stream.write_all(reply.as_bytes()).is_err() {
+                return false;
+            }
+        }
+
+        if buffer.len() > MAX_LINE {
+            let _ = stream.write_all(b"error: line too long\n");
+            return false;
+        }
+    }
+}
+
+fn handle(client: &mut Client, source: &ConfigSource, line: &str) -> Result<(), String> {
+    let line = line.trim();
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, Some(argument.trim())),
+        None => (line, None),
+    };
+
+    match command {
+        "show" | "hide" | "toggle" => {
+            let visible = match command {
+                "show" => Some(true),
+                "hide" => Some(false),
+                _ => None,
+            };
+
+            match client.set_visible(argument, visible) {
+                0 => Err(format!("no bar has the namespace `{}`", argument.unwrap_or_default())),
+                _ => Ok(()),
+            }
+        }
+        "reload" => source.reload(client).map_err(|error| error.to_string()),
+        "refresh" => {
+            let modules = match argument {
+                Some(name) => named_modules(source, name)?,
+                None => client.shown_modules(),
+            };
+
+            client.refresh_modules(&modules);
+            Ok(())
+        }
+        "set" => {
+            let argument = argument.ok_or("usage: set <module> <value>")?;
+            let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+
+            let modules = named_modules(source, name)?;
+            let changed: Vec<_> = modules
+                .into_iter()
+                .filter(|module| module.borrow_mut().set_value(value.trim_start()))
+                .collect();
+
+            client.mark_changed(&changed);
+            Ok(())
+        }
+        "" => Err("empty command".to_string()),
+        _ => Err(format!("unknown command `{command}`")),
+    }
+}
+
+fn named_modules(source: &ConfigSource, name: &str) -> Result<Vec<ModuleHandle>, String> {
+    let modules = source.modules_named(name);
+    if modules.is_empty() {
+        return Err(format!("no module is named `{name}`"));
+    }
+
+    Ok(modules)
+}
+
+/// Sends `command` to the running instance, returning its answer.
+pub fn send(command: &str) -> io::Result<String> {
+    let path = socket_path().ok_or_else(|| io::Error::new(ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|err| io::Error::new(err.kind(), format!("cannot connect to {}: {err}", path.display())))?;
+
+    stream.write_all(format!("{}\n", command.replace('\n', " ")).as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
@@ -0,0 +1,307 @@
+use crate::canvas::Rect;
+use crate::modules::*;
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Insets {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Insets {
+    pub fn all(value: u32) -> Self {
+        Self { top: value, right: value, bottom: value, left: value }
+    }
+
+    pub fn symmetric(vertical: u32, horizontal: u32) -> Self {
+        Self { top: vertical, right: horizontal, bottom: vertical, left: horizontal }
+    }
+
+    fn horizontal(&self) -> u32 {
+        self.left + self.right
+    }
+
+    fn vertical(&self) -> u32 {
+        self.top + self.bottom
+    }
+}
+
+/// The direction modules are laid out in, along the bar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+/// Placement of a module across the bar. On vertical bars `Top` and `Bottom` are the left and right edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerticalAlign {
+    Top,
+    Center,
+    Bottom,
+    /// Stretch over the whole thickness of the bar, ignoring the module's own extent across it
+    #[default]
+    Fill,
+}
+
+/// How a module is placed within its group, following the CSS box model:
+/// the content is surrounded by padding, which is surrounded by margin.
+/// `min_width` and `max_width` bound the content plus padding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModuleStyle {
+    pub padding: Insets,
+    pub margin: Insets,
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub align: VerticalAlign,
+    /// Share of the space left over on the bar this module takes, 0 meaning none
+    pub grow: u32,
+    /// Modules with a lower priority are hidden first when the bar runs out of space
+    pub priority: i32,
+}
+
+impl ModuleStyle {
+    fn clamp_width(&self, width: u32) -> u32 {
+        let width = width.max(self.min_width.unwrap_or(0));
+        width.min(self.max_width.unwrap_or(u32::MAX))
+    }
+}
+
+/// Where a module ended up: `rect` covers the module including its padding,
+/// `content` is the area it draws into. Both are relative to the laid out canvas,
+/// with x running along the bar and y across it.
+pub(crate) struct Placement {
+    pub(crate) group: usize,
+    pub(crate) index: usize,
+    pub(crate) rect: Rect,
+    pub(crate) content: Rect,
+}
+
+struct Item {
+    group: usize,
+    index: usize,
+    style: ModuleStyle,
+    content_height: Option<u32>,
+    /// Width including padding
+    width: u32,
+    visible: bool,
+}
+
+impl Item {
+    fn outer_width(&self) -> u32 {
+        self.width + self.style.margin.horizontal()
+    }
+}
+
+/// Lays out three groups of modules along a bar `width` long and `height` thick.
+/// Vertical bars are laid out the same way, the caller swaps the axes of the placements.
+///
+/// While the modules do not fit, the lowest priority ones are hidden, the most recently
+/// added first on ties; a single module wider than the bar is truncated. Space left over
+/// is shared between growing modules, and the center group is centered on the bar unless
+/// that would make it overlap the left or right group.
+pub(crate) fn layout(groups: [&Modules; 3], width: u32, height: u32, orientation: Orientation) -> Vec<Placement> {
+    let mut items: Vec<Item> = Vec::new();
+    for (group, modules) in groups.iter().enumerate() {
+        for (index, entry) in modules.modules.iter().enumerate() {
+            let (length, thickness) = entry.module.borrow().get_extent(orientation);
+            let style = entry.style;
+
+            items.push(Item {
+                group,
+                index,
+                style,
+                content_height: thickness,
+                width: style.clamp_width(length + style.padding.horizontal()),
+                visible: true,
+            });
+        }
+    }
+
+    let total_width = |items: &[Item]| -> u32 {
+        items.iter().filter(|item| item.visible).map(Item::outer_width).sum()
+    };
+
+    while total_width(&items) > width && items.iter().filter(|item| item.visible).count() > 1 {
+        let lowest = items
+            .iter_mut()
+            .rev()
+            .filter(|item| item.visible)
+            .reduce(|lowest, item| if item.style.priority < lowest.style.priority { item } else { lowest });
+
+        if let Some(item) = lowest {
+            item.visible = false;
+        }
+    }
+
+    items.retain(|item| item.visible);
+
+    // Share what is left among growing modules, respecting their maximum width
+    let mut remaining = width.saturating_sub(total_width(&items));
+    loop {
+        let growing: Vec<usize> = (0..items.len())
+            .filter(|&i| items[i].style.grow > 0 && items[i].width < items[i].style.max_width.unwrap_or(u32::MAX))
+            .collect();
+        let total_grow: u32 = growing.iter().map(|&i| items[i].style.grow).sum();
+        if remaining == 0 || total_grow == 0 {
+            break;
+        }
+
+        let available = remaining;
+        for &i in &growing {
+            let share = (available as u64 * items[i].style.grow as u64 / total_grow as u64) as u32;
+            let grown = items[i].style.clamp_width(items[i].width + share.max(1).min(remaining));
+            remaining -= grown - items[i].width;
+            items[i].width = grown;
+        }
+
+        if remaining == available {
+            break;
+        }
+    }
+
+    let group_width = |group: usize| -> u32 {
+        items.iter().filter(|item| item.group == group).map(Item::outer_width).sum()
+    };
+
+    let left_width = group_width(0);
+    let center_width = group_width(1);
+    let right_width = group_width(2);
+
+    // Centered on the bar, but pushed aside by the left and right groups
+    let center_max = width.saturating_sub(right_width + center_width);
+    let center_x = (width.saturating_sub(center_width) / 2).min(center_max).max(left_width);
+
+    let starts = [0, center_x, width.saturating_sub(right_width)];
+    let mut cursors = starts;
+
+    let mut placements = Vec::new();
+    for item in &items {
+        let style = &item.style;
+        let cursor = &mut cursors[item.group];
+
+        let x = (*cursor + style.margin.left).min(width);
+        let item_width = item.width.min(width - x);
+        *cursor += item.outer_width();
+
+        let available_height = height.saturating_sub(style.margin.vertical());
+        let item_height = match (style.align, item.content_height) {
+            (VerticalAlign::Fill, _) | (_, None) => available_height,
+            (_, Some(content_height)) => (content_height + style.padding.vertical()).min(available_height),
+        };
+
+        let y = style.margin.top + match style.align {
+            VerticalAlign::Top | VerticalAlign::Fill => 0,
+            VerticalAlign::Center => (available_height - item_height) / 2,
+            VerticalAlign::Bottom => available_height - item_height,
+        };
+
+        let rect = Rect::new(x as i32, y as i32, item_width, item_height);
+        let content = Rect::new(
+            (x + style.padding.left.min(item_width)) as i32,
+            (y + style.padding.top.min(item_height)) as i32,
+            item_width.saturating_sub(style.padding.horizontal()),
+            item_height.saturating_sub(style.padding.vertical()),
+        );
+
+        placements.push(Placement {
+            group: item.group,
+            index: item.index,
+            rect,
+            content,
+        });
+    }
+
+    placements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modules(widths: &[(u32, ModuleStyle)]) -> Modules {
+        widths
+            .iter()
+            .fold(Modules::new(), |modules, &(width, style)| modules.add_styled(SpacingModule { width }, style))
+    }
+
+    fn priority(priority: i32) -> ModuleStyle {
+        ModuleStyle { priority, ..ModuleStyle::default() }
+    }
+
+    /// Group, index and horizontal extent of every placed module.
+    fn place(groups: [&Modules; 3], width: u32) -> Vec<(usize, usize, i32, u32)> {
+        layout(groups, width, 20, Orientation::Horizontal)
+            .iter()
+            .map(|placement| (placement.group, placement.index, placement.rect.x, placement.rect.width))
+            .collect()
+    }
+
+    #[test]
+    fn hides_lowest_priority_first() {
+        let left = modules(&[(40, priority(0)), (40, priority(1))]);
+        let right = modules(&[(40, priority(2))]);
+
+        assert_eq!(place([&left, &Modules::new(), &right], 100), vec![(0, 1, 0, 40), (2, 0, 60, 40)]);
+    }
+
+    #[test]
+    fn hides_most_recently_added_on_ties() {
+        let left = modules(&[(40, priority(0)), (40, priority(0)), (40, priority(0))]);
+
+        assert_eq!(place([&left, &Modules::new(), &Modules::new()], 100), vec![(0, 0, 0, 40), (0, 1, 40, 40)]);
+    }
+
+    #[test]
+    fn truncates_single_module_wider_than_bar() {
+        let center = modules(&[(150, priority(0))]);
+
+        assert_eq!(place([&Modules::new(), &center, &Modules::new()], 100), vec![(1, 0, 0, 100)]);
+    }
+
+    #[test]
+    fn grows_up_to_max_width() {
+        let capped = ModuleStyle { grow: 1, max_width: Some(30), ..ModuleStyle::default() };
+        let growing = ModuleStyle { grow: 1, ..ModuleStyle::default() };
+        let left = modules(&[(10, capped), (10, growing)]);
+
+        assert_eq!(place([&left, &Modules::new(), &Modules::new()], 100), vec![(0, 0, 0, 30), (0, 1, 30, 70)]);
+    }
+
+    #[test]
+    fn centers_center_group() {
+        let side = modules(&[(10, priority(0))]);
+        let center = modules(&[(50, priority(0))]);
+
+        assert_eq!(place([&side, &center, &side], 200), vec![(0, 0, 0, 10), (1, 0, 75, 50), (2, 0, 190, 10)]);
+    }
+
+    #[test]
+    fn pushes_center_group_aside() {
+        let wide = modules(&[(100, priority(0))]);
+        let center = modules(&[(50, priority(0))]);
+        let none = Modules::new();
+
+        assert_eq!(place([&wide, &center, &none], 200), vec![(0, 0, 0, 100), (1, 0, 100, 50)]);
+        assert_eq!(place([&none, &center, &wide], 200), vec![(1, 0, 50, 50), (2, 0, 100, 100)]);
+    }
+
+    #[test]
+    fn insets_content_by_padding_and_rect_by_margin() {
+        let style = ModuleStyle {
+            padding: Insets::symmetric(2, 3),
+            margin: Insets::all(1),
+            ..ModuleStyle::default()
+        };
+        let left = modules(&[(10, style)]);
+
+        let placements = layout([&left, &Modules::new(), &Modules::new()], 100, 20, Orientation::Horizontal);
+        assert_eq!(placements[0].rect, Rect::new(1, 1, 16, 18));
+        assert_eq!(placements[0].content, Rect::new(4, 3, 10, 14));
+    }
+}
@@ -0,0 +1,68 @@
+//! Building blocks for status bars on Wayland compositors implementing wlr-layer-shell.
+//!
+//! A [`Client`] connects to the compositor and shows bars placed by a [`BarConfig`], each
+//! drawn by a callback on a [`Canvas`]. Modules implement [`Module`] and are laid out in
+//! three groups by [`Canvas::draw_module_groups`], which also routes input to them.
+//!
+//! ```no_run
+//! use std::rc::Rc;
+//!
+//! use ruwabar::{BarConfig, BarPosition, Client, ClockModule, Font, FontSettings, Modules};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let bytes = std::fs::read("/usr/share/fonts/TTF/DejaVuSansMono.ttf")?;
+//!     let font = Rc::new(Font::from_bytes(bytes, FontSettings::default())?);
+//!
+//!     let left = Modules::new();
+//!     let center = Modules::new().add(ClockModule::new("%H:%M", 0xffffffff, font, 16.0));
+//!     let right = Modules::new();
+//!
+//!     let mut client = Client::new()?;
+//!     client.add_bar(BarConfig::new(BarPosition::Top, 30), move |canvas| {
+//!         canvas.fill(0xff202020);
+//!         canvas.draw_module_groups(&left, &center, &right);
+//!     });
+//!
+//!     client.start()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`ConfigSource`] builds the bars from a config file instead, and [`ipc`] and [`signals`]
+//! let other programs control a running client.
+
+mod bar;
+mod buffer;
+mod canvas;
+mod client;
+mod damage;
+mod error;
+mod event_loop;
+mod input;
+mod layout;
+mod modules;
+mod output;
+mod path;
+mod reload;
+mod state;
+
+pub mod config;
+pub mod ipc;
+pub mod signals;
+
+pub use bar::{BarConfig, BarLayer, BarOutput, BarPosition, DrawFn, ExclusiveZone, KeyboardInteractivity};
+pub use canvas::{BlendMode, Canvas, ModuleSlot, Rect};
+pub use client::Client;
+pub use config::ConfigError;
+pub use error::{Error, Result};
+pub use input::{ScrollAxis, TouchGesture};
+pub use layout::{Insets, ModuleStyle, Orientation, VerticalAlign};
+pub use modules::{
+    ClockModule, ColorModule, Module, ModuleHandle, ModulePosition, Modules, SpacingModule, TextModule,
+    ToggleModule, VerticalText, BUTTON_LEFT, BUTTON_MIDDLE, BUTTON_RIGHT,
+};
+pub use output::Output;
+pub use path::{FillRule, LineCap, LineJoin, Path};
+pub use reload::ConfigSource;
+
+pub use fontdue::{Font, FontSettings};
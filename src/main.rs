@@ -1,49 +1,99 @@
-mod bar;
-mod canvas;
-mod client;
-mod state;
-mod modules;
+//! The `ruwabar` binary: bars from a config file, controlled through `ruwabar msg` and signals.
 
-use bar::BarPosition;
-use client::Client;
-use modules::*;
+use ruwabar::{config, ipc, signals, Client, ConfigSource};
 
-use fontdue::{Font, FontSettings};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
 
-fn main() {
-    let mut client = Client::new();
+/// How long to wait for the compositor to come back when the connection is lost.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
-    let font = Font::from_bytes(include_bytes!("/usr/share/fonts/TTF/HackNerdFontMono-Regular.ttf") as &[u8], FontSettings::default()).unwrap();
+const USAGE: &str = "usage: ruwabar [--config <path>]\n       ruwabar msg <command>";
 
-    let c1 = 0xFFCF4345u32;
-    let c2 = 0xFF44848Cu32;
-    let c3 = 0xFF181818u32;
-    let c4 = 0xFFBA1245u32;
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut config_path = None;
 
-    let transparent = 0x0u32;
-    let black = 0xFF000000u32;
+    if args.peek().is_some_and(|arg| arg == "msg") {
+        return message(&args.skip(1).collect::<Vec<_>>().join(" "));
+    }
 
-    let modules_top_left = Modules::new()
-        .add(SpacingModule { width: 5 })
-        .add(ColorModule { width: 40, color: 0xFFFF0018u32 })
-        .add(SpacingModule { width: 5 })
-        .add(ColorModule { width: 40, color: 0xFF00FF18u32 });
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--config" => match args.next() {
+                Some(path) => config_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("ruwabar: {arg} needs a path");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => {
+                eprintln!("ruwabar: unknown argument `{arg}`\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
 
-    client.add_bar(BarPosition::Top, 40, move |canvas| {
-        canvas.fill(c1 & 0x7FFFFFFF);
-        canvas.draw_modules(&modules_top_left, ModulePosition::Left);
+    // An explicitly given config has to exist, without the default one the built-in config is used
+    if let Some(path) = config_path.as_ref().filter(|path| !path.exists()) {
+        eprintln!("ruwabar: {} does not exist", path.display());
+        return ExitCode::FAILURE;
+    }
+
+    match run(ConfigSource::new(config_path.or_else(config::default_path))) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ruwabar: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Shows the bars of the config until told to stop.
+fn run(source: ConfigSource) -> ruwabar::Result<()> {
+    let setup = source.load()?;
+
+    let mut client = Client::new()?;
+    client.set_reconnect_timeout(Some(RECONNECT_TIMEOUT));
+    client.set_error_handler(|error| match error {
+        ruwabar::Error::Config(error) => eprintln!("ruwabar: keeping the previous config, {error}"),
+        error if error.is_connection_lost() => eprintln!("ruwabar: {error}, reconnecting"),
+        error => eprintln!("ruwabar: {error}"),
     });
+    source.apply(&mut client, setup);
 
-    client.add_bar(BarPosition::Bottom, 40, move |canvas| {
-        canvas.fill(c2);
+    if let Err(error) = source.watch(&mut client) {
+        eprintln!("ruwabar: cannot watch the config for changes: {error}");
+    }
 
-        canvas.fill_rounded_rect(5, 5, 100, 30, 15, c3);
-        canvas.fill_rounded_rect(10, 10, 90, 20, 10, c4);
+    if let Err(error) = ipc::listen(&mut client, source.clone()) {
+        eprintln!("ruwabar: cannot open the control socket, `ruwabar msg` will not work: {error}");
+    }
 
-        canvas.fill_oval(350, 5, 30, 30, transparent);
+    if let Err(error) = signals::handle(&mut client, source.clone()) {
+        eprintln!("ruwabar: cannot handle signals: {error}");
+    }
 
-        canvas.draw_string(120, 30, "Hello, World!", black, &font, 20.0);
-    });
+    client.start()
+}
+
+/// `ruwabar msg`, sends a command to the running instance.
+fn message(command: &str) -> ExitCode {
+    if command.is_empty() || command == "help" {
+        println!("usage: ruwabar msg <command>\n\n{}", ipc::USAGE);
+        return ExitCode::SUCCESS;
+    }
 
-    client.start();
+    match ipc::send(command) {
+        Ok(reply) if reply == "ok" => ExitCode::SUCCESS,
+        Ok(reply) => {
+            eprintln!("ruwabar: {}", reply.strip_prefix("error: ").unwrap_or(&reply));
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("ruwabar: {error}");
+            ExitCode::FAILURE
+        }
+    }
 }
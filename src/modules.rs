@@ -1,19 +1,92 @@
 use crate::canvas::Canvas;
+use crate::input::{ScrollAxis, TouchGesture};
+use crate::layout::{ModuleStyle, Orientation};
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use fontdue::Font;
+use serde::Deserialize;
 
 pub trait Module {
+    /// Width of the module's content, without padding or margins.
     fn get_width(&self) -> u32;
+
+    /// Height of the module's content, `None` to take the whole height of the bar.
+    fn get_height(&self) -> Option<u32> {
+        None
+    }
+
+    /// Extent of the module's content along a bar laid out in `orientation`, and across it
+    /// unless it takes the whole thickness. Modules are as long on vertical bars as they are
+    /// wide on horizontal ones unless they override this.
+    fn get_extent(&self, _orientation: Orientation) -> (u32, Option<u32>) {
+        (self.get_width(), self.get_height())
+    }
+
     fn draw(&self, canvas: &mut Canvas);
+
+    /// When `update` should be called next, `None` if the module never changes on its own.
+    fn next_update(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Refreshes the module's state, returning whether it has to be redrawn.
+    /// Also called out of schedule when the module is refreshed from outside.
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    /// Takes a value sent from outside the bar, returning whether it has to be redrawn.
+    fn set_value(&mut self, _value: &str) -> bool {
+        false
+    }
+
+    /// A pointer button was pressed over the module, `x` and `y` are relative to its slot.
+    /// Buttons are Linux input event codes such as `BUTTON_LEFT`.
+    fn on_click(&mut self, _button: u32, _x: f64, _y: f64) {}
+
+    /// The pointer scrolled over the module, positive deltas point down or right.
+    fn on_scroll(&mut self, _axis: ScrollAxis, _delta: f64) {}
+
+    fn on_enter(&mut self) {}
+    fn on_leave(&mut self) {}
+
+    /// A touch gesture on the module. By default taps and long presses act as left and
+    /// right clicks and swipes scroll horizontally, opposite to the finger like on touch screens.
+    fn on_touch(&mut self, gesture: TouchGesture) {
+        match gesture {
+            TouchGesture::Tap { x, y } => self.on_click(BUTTON_LEFT, x, y),
+            TouchGesture::LongPress { x, y } => self.on_click(BUTTON_RIGHT, x, y),
+            TouchGesture::Swipe { dx } => self.on_scroll(ScrollAxis::Horizontal, -dx),
+        }
+    }
 }
 
-#[allow(dead_code)]
+pub const BUTTON_LEFT: u32 = 0x110;
+pub const BUTTON_RIGHT: u32 = 0x111;
+pub const BUTTON_MIDDLE: u32 = 0x112;
+
+pub type ModuleHandle = Rc<RefCell<dyn Module>>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ModulePosition {
     Left,
     Center,
     Right
 }
 
+#[derive(Clone)]
+pub(crate) struct ModuleEntry {
+    pub(crate) module: ModuleHandle,
+    pub(crate) style: ModuleStyle,
+}
+
+#[derive(Clone, Default)]
 pub struct Modules {
-    pub(crate) modules: Vec<Box<dyn Module>>,
+    pub(crate) modules: Vec<ModuleEntry>,
 }
 
 impl Modules {
@@ -23,8 +96,22 @@ impl Modules {
         }
     }
 
-    pub fn add(mut self, module: impl Module + 'static) -> Self {
-        self.modules.push(Box::new(module));
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, module: impl Module + 'static) -> Self {
+        self.add_styled(module, ModuleStyle::default())
+    }
+
+    pub fn add_with_priority(self, module: impl Module + 'static, priority: i32) -> Self {
+        self.add_styled(module, ModuleStyle { priority, ..ModuleStyle::default() })
+    }
+
+    pub fn add_styled(self, module: impl Module + 'static, style: ModuleStyle) -> Self {
+        self.add_shared(Rc::new(RefCell::new(module)), style)
+    }
+
+    /// Adds a module that is also held elsewhere, e.g. to show it on several bars.
+    pub fn add_shared(mut self, module: ModuleHandle, style: ModuleStyle) -> Self {
+        self.modules.push(ModuleEntry { module, style });
         self
     }
 }
@@ -45,8 +132,256 @@ pub struct ColorModule {
 
 impl Module for ColorModule {
     fn get_width(&self) -> u32 { self.width }
-    
+
     fn draw(&self, canvas: &mut Canvas) {
         canvas.fill(self.color);
     }
 }
+
+/// A color swatch switched between two colors by left clicks.
+pub struct ToggleModule {
+    pub width: u32,
+    pub on_color: u32,
+    pub off_color: u32,
+    pub on: bool,
+}
+
+impl Module for ToggleModule {
+    fn get_width(&self) -> u32 { self.width }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.fill(if self.on { self.on_color } else { self.off_color });
+    }
+
+    fn on_click(&mut self, button: u32, _x: f64, _y: f64) {
+        if button == BUTTON_LEFT {
+            self.on = !self.on;
+        }
+    }
+}
+
+/// A red badge shown while the config file has errors, taking no space otherwise.
+#[derive(Default)]
+pub struct ErrorIndicatorModule {
+    pub(crate) error: Option<String>,
+}
+
+impl ErrorIndicatorModule {
+    const SIZE: u32 = 20;
+}
+
+impl Module for ErrorIndicatorModule {
+    fn get_width(&self) -> u32 {
+        if self.error.is_some() { Self::SIZE } else { 0 }
+    }
+
+    fn get_height(&self) -> Option<u32> {
+        Some(Self::SIZE)
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        if self.error.is_none() {
+            return;
+        }
+
+        let size = canvas.width().min(canvas.height()) as f32;
+        canvas.fill_rounded_rect(0.0, 0.0, size, size, size / 4.0, 0xFFE0252B);
+
+        // An exclamation mark, drawn without a font since the font may be what is broken
+        let stroke = (size / 8.0).max(1.0);
+        let x = (size - stroke) / 2.0;
+        canvas.fill_rect(x, size * 0.2, stroke, size * 0.4, 0xFFFFFFFF);
+        canvas.fill_rect(x, size * 0.7, stroke, stroke, 0xFFFFFFFF);
+    }
+}
+
+/// Size of a line of text along and across a bar, see `Module::get_extent`.
+fn text_extent(
+    text: &str,
+    font: &Font,
+    size: f32,
+    vertical_text: VerticalText,
+    orientation: Orientation,
+) -> (u32, Option<u32>) {
+    let line_height = Canvas::text_height(font, size);
+
+    match (orientation, vertical_text) {
+        (Orientation::Horizontal, _) | (Orientation::Vertical, VerticalText::Rotated) => {
+            (Canvas::text_width(text, font, size), Some(line_height))
+        }
+        (Orientation::Vertical, VerticalText::Stacked) => {
+            let widest = text.chars()
+                .map(|c| Canvas::text_width(c.encode_utf8(&mut [0; 4]), font, size))
+                .max()
+                .unwrap_or(0);
+
+            (text.chars().count() as u32 * line_height, Some(widest))
+        }
+    }
+}
+
+/// Draws a line of text centered across the bar, turned or stacked on vertical bars.
+fn draw_text(canvas: &mut Canvas, text: &str, color: u32, font: &Font, size: f32, vertical_text: VerticalText) {
+    match (canvas.orientation(), vertical_text) {
+        (Orientation::Horizontal, _) => {
+            let baseline = canvas.text_baseline(font, size);
+            canvas.draw_string(0.0, baseline, text, color, font, size);
+        }
+        (Orientation::Vertical, VerticalText::Rotated) => {
+            let x = canvas.width().saturating_sub(Canvas::text_height(font, size)) / 2;
+            canvas.draw_string_rotated(x as f32, 0.0, text, color, font, size);
+        }
+        (Orientation::Vertical, VerticalText::Stacked) => {
+            let line_height = Canvas::text_height(font, size);
+            let ascent = font.horizontal_line_metrics(size).map_or(size, |metrics| metrics.ascent) as u32;
+
+            for (row, c) in text.chars().enumerate() {
+                let c = c.encode_utf8(&mut [0; 4]).to_string();
+                let x = canvas.width().saturating_sub(Canvas::text_width(&c, font, size)) / 2;
+                let baseline = row as u32 * line_height + ascent;
+                canvas.draw_string(x as f32, baseline as f32, &c, color, font, size);
+            }
+        }
+    }
+}
+
+/// Text pushed from outside, e.g. by scripts through `ruwabar msg set`.
+pub struct TextModule {
+    text: String,
+    color: u32,
+    font: Rc<Font>,
+    size: f32,
+    vertical_text: VerticalText,
+}
+
+impl TextModule {
+    pub fn new(text: &str, color: u32, font: Rc<Font>, size: f32) -> Self {
+        Self {
+            text: text.to_string(),
+            color,
+            font,
+            size,
+            vertical_text: VerticalText::default(),
+        }
+    }
+
+    pub fn vertical_text(mut self, vertical_text: VerticalText) -> Self {
+        self.vertical_text = vertical_text;
+        self
+    }
+}
+
+impl Module for TextModule {
+    fn get_width(&self) -> u32 {
+        Canvas::text_width(&self.text, &self.font, self.size)
+    }
+
+    fn get_height(&self) -> Option<u32> {
+        Some(Canvas::text_height(&self.font, self.size))
+    }
+
+    fn get_extent(&self, orientation: Orientation) -> (u32, Option<u32>) {
+        text_extent(&self.text, &self.font, self.size, self.vertical_text, orientation)
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        draw_text(canvas, &self.text, self.color, &self.font, self.size, self.vertical_text);
+    }
+
+    fn set_value(&mut self, value: &str) -> bool {
+        if value == self.text {
+            return false;
+        }
+
+        self.text = value.to_string();
+        true
+    }
+}
+
+/// How text modules write on vertical bars.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerticalText {
+    /// Turned clockwise, read from top to bottom
+    #[default]
+    Rotated,
+    /// Upright characters stacked one below the other
+    Stacked,
+}
+
+pub struct ClockModule {
+    format: String,
+    color: u32,
+    font: Rc<Font>,
+    size: f32,
+    vertical_text: VerticalText,
+
+    text: String,
+    next_tick: Instant,
+}
+
+impl ClockModule {
+    /// A clock showing the local time formatted by the strftime `format`. A format chrono cannot
+    /// parse is shown as it is, in place of the time.
+    pub fn new(format: &str, color: u32, font: Rc<Font>, size: f32) -> Self {
+        let mut clock = Self {
+            format: format.to_string(),
+            color,
+            font,
+            size,
+            vertical_text: VerticalText::default(),
+
+            text: String::new(),
+            next_tick: Instant::now(),
+        };
+        clock.update();
+        clock
+    }
+
+    pub fn vertical_text(mut self, vertical_text: VerticalText) -> Self {
+        self.vertical_text = vertical_text;
+        self
+    }
+}
+
+impl Module for ClockModule {
+    fn get_width(&self) -> u32 {
+        Canvas::text_width(&self.text, &self.font, self.size)
+    }
+
+    fn get_height(&self) -> Option<u32> {
+        Some(Canvas::text_height(&self.font, self.size))
+    }
+
+    fn get_extent(&self, orientation: Orientation) -> (u32, Option<u32>) {
+        text_extent(&self.text, &self.font, self.size, self.vertical_text, orientation)
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        draw_text(canvas, &self.text, self.color, &self.font, self.size, self.vertical_text);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        Some(self.next_tick)
+    }
+
+    fn update(&mut self) -> bool {
+        // Wake up right at the start of the next wall-clock second
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let until_next_second = Duration::from_secs(1) - Duration::from_nanos(since_epoch.subsec_nanos() as u64);
+        self.next_tick = Instant::now() + until_next_second;
+
+        // `to_string` would panic on an invalid format
+        let mut text = String::new();
+        if write!(text, "{}", chrono::Local::now().format(&self.format)).is_err() {
+            text.clone_from(&self.format);
+        }
+
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+}
@@ -0,0 +1,72 @@
+use wayland_client::{
+    protocol::*,
+    Proxy,
+};
+
+use wayland_protocols::xdg::xdg_output::zv1::client::*;
+
+#[derive(Debug)]
+pub struct Output {
+    pub(crate) global_name: u32,
+    pub(crate) wl_output: wl_output::WlOutput,
+    pub(crate) xdg_output: Option<zxdg_output_v1::ZxdgOutputV1>,
+
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) logical_size: Option<(u32, u32)>,
+    pub(crate) mode_size: Option<(u32, u32)>,
+    pub(crate) scale: i32,
+
+    pub(crate) ready: bool,
+}
+
+impl Output {
+    pub(crate) fn new(global_name: u32, wl_output: wl_output::WlOutput) -> Self {
+        Self {
+            global_name,
+            wl_output,
+            xdg_output: None,
+
+            name: None,
+            description: None,
+            logical_size: None,
+            mode_size: None,
+            scale: 1,
+
+            ready: false,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Size of the output in surface-local coordinates, preferring the size
+    /// reported by xdg-output over the current mode divided by the scale.
+    pub fn logical_size(&self) -> Option<(u32, u32)> {
+        self.logical_size.or_else(|| {
+            self.mode_size.map(|(width, height)| {
+                let scale = self.scale.max(1) as u32;
+                (width / scale, height / scale)
+            })
+        })
+    }
+
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    pub(crate) fn destroy(&self) {
+        if let Some(xdg_output) = &self.xdg_output {
+            xdg_output.destroy();
+        }
+
+        if self.wl_output.version() >= 3 {
+            self.wl_output.release();
+        }
+    }
+}
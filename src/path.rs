@@ -0,0 +1,706 @@
+use std::f64::consts::PI;
+
+use crate::canvas::{pixel_bounds, Rect};
+
+/// Curves are flattened into lines straying from them by at most this many device pixels.
+const TOLERANCE: f64 = 0.1;
+
+/// Curves are never flattened into more lines than this, however large they are.
+const MAX_CURVE_LINES: usize = 1024;
+
+/// Miter joins sticking out further than this many times the stroke width are beveled, as in SVG.
+const MITER_LIMIT: f64 = 4.0;
+
+/// Rows sampled within each pixel, coverage along a row is computed exactly.
+const SAMPLES: usize = 16;
+
+/// Dashing stops after this many dashes, so that a path reaching far off the canvas cannot stall a frame.
+const MAX_DASHES: usize = 1 << 16;
+
+type Point = (f64, f64);
+
+/// Which parts of a path that overlaps itself are inside it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside is wherever the outline goes around more often one way than the other
+    #[default]
+    NonZero,
+    /// Inside is wherever the outline goes around an odd number of times, leaving holes where subpaths overlap
+    EvenOdd,
+}
+
+/// How the ends of open subpaths and of dashes look when stroked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// Cut off square at the ends
+    #[default]
+    Butt,
+    /// Rounded off by half circles around the ends
+    Round,
+    /// Cut off square, half the stroke width beyond the ends
+    Square,
+}
+
+/// How a stroke goes around corners.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Sharp corners, beveled where they would stick out over four times the stroke width
+    #[default]
+    Miter,
+    Round,
+    /// Corners cut off straight
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Segment {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    QuadTo(f32, f32, f32, f32),
+    CubicTo(f32, f32, f32, f32, f32, f32),
+    Close,
+}
+
+/// An outline made of lines and curves in logical coordinates, filled by `Canvas::fill_path` and
+/// stroked by `Canvas::draw_path`. It consists of subpaths, each started by `move_to` and possibly
+/// ended by `close`.
+///
+/// Unlike with `Canvas::draw_line`, coordinates are not pixel centers: a line from 0, 0 to 10, 0
+/// runs along the top edge of the canvas.
+///
+/// ```
+/// # use ruwabar::Path;
+/// // A powerline separator, pointing right
+/// let mut chevron = Path::new();
+/// chevron.move_to(0.0, 0.0).line_to(8.0, 12.0).line_to(0.0, 24.0).close();
+///
+/// // Three quarters of a progress ring, starting at the top and going clockwise
+/// let mut progress = Path::new();
+/// progress.arc(12.0, 12.0, 10.0, 90.0, -270.0);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    segments: Vec<Segment>,
+    start: Option<(f32, f32)>, // of the current subpath
+    current: Option<(f32, f32)>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Starts a new subpath at `x`, `y`.
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.segments.push(Segment::MoveTo(x, y));
+        self.start = Some((x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    /// Adds a straight line to `x`, `y`, or starts a subpath there if there is none.
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        if self.current.is_none() {
+            return self.move_to(x, y);
+        }
+
+        self.segments.push(Segment::LineTo(x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    /// Adds a quadratic Bézier curve to `x`, `y`, pulled towards `control_x`, `control_y`.
+    pub fn quad_to(&mut self, control_x: f32, control_y: f32, x: f32, y: f32) -> &mut Self {
+        if self.current.is_none() {
+            self.move_to(control_x, control_y);
+        }
+
+        self.segments.push(Segment::QuadTo(control_x, control_y, x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    /// Adds a cubic Bézier curve to `x`, `y`, leaving the current point towards the first
+    /// control point and arriving from the direction of the second.
+    pub fn cubic_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) -> &mut Self {
+        if self.current.is_none() {
+            self.move_to(x1, y1);
+        }
+
+        self.segments.push(Segment::CubicTo(x1, y1, x2, y2, x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    /// Adds the part of the circle around `center_x`, `center_y` that starts at `start_angle` and spans
+    /// `sweep_angle`, joined to the current point by a line. Angles are in degrees, 0 pointing right
+    /// and positive ones going counterclockwise, as with `Canvas::draw_arc`.
+    pub fn arc(&mut self, center_x: f32, center_y: f32, radius: f32, start_angle: f32, sweep_angle: f32) -> &mut Self {
+        let (center_x, center_y, radius) = (center_x as f64, center_y as f64, radius as f64);
+        let point = |angle: f64| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            (center_x + radius * cos, center_y - radius * sin)
+        };
+
+        let start = point(start_angle as f64);
+        self.line_to(start.0 as f32, start.1 as f32);
+
+        // A cubic curve is close to an arc of up to a quarter circle, given control points along the
+        // tangents at the ends, this far from them
+        let sweep = (sweep_angle as f64).clamp(-360.0, 360.0);
+        let count = (sweep.abs() / 90.0).ceil().max(1.0) as usize;
+        let step = sweep / count as f64;
+        let handle = 4.0 / 3.0 * (step.to_radians() / 4.0).tan() * radius;
+
+        for i in 0..count {
+            let from = start_angle as f64 + step * i as f64;
+            let to = from + step;
+            let ((sin_from, cos_from), (sin_to, cos_to)) = (from.to_radians().sin_cos(), to.to_radians().sin_cos());
+            let (start, end) = (point(from), point(to));
+
+            self.cubic_to(
+                (start.0 - handle * sin_from) as f32,
+                (start.1 - handle * cos_from) as f32,
+                (end.0 + handle * sin_to) as f32,
+                (end.1 + handle * cos_to) as f32,
+                end.0 as f32,
+                end.1 as f32,
+            );
+        }
+
+        self
+    }
+
+    /// Ends the current subpath with a straight line back to where it started.
+    pub fn close(&mut self) -> &mut Self {
+        if self.current.is_some() && self.segments.last() != Some(&Segment::Close) {
+            self.segments.push(Segment::Close);
+            self.current = self.start;
+        }
+
+        self
+    }
+
+    /// The subpaths in device coordinates, with curves flattened into lines.
+    pub(crate) fn flatten(&self, scale: f64) -> Vec<Polyline> {
+        let device = |x: f32, y: f32| (x as f64 * scale, y as f64 * scale);
+
+        let mut polylines = Vec::new();
+        let mut polyline = Polyline::default();
+        // Where a subpath continuing after `close` starts
+        let mut start = (0.0, 0.0);
+
+        let finish = |polylines: &mut Vec<Polyline>, polyline: &mut Polyline| {
+            let polyline = std::mem::take(polyline);
+            if polyline.points.len() > 1 {
+                polylines.push(polyline);
+            }
+        };
+
+        for segment in &self.segments {
+            if polyline.points.is_empty() && !matches!(segment, Segment::MoveTo(..)) {
+                polyline.points.push(start);
+            }
+            let from = polyline.points.last().copied().unwrap_or(start);
+
+            match *segment {
+                Segment::MoveTo(x, y) => {
+                    finish(&mut polylines, &mut polyline);
+                    start = device(x, y);
+                    polyline.points.push(start);
+                }
+                Segment::LineTo(x, y) => polyline.points.push(device(x, y)),
+                Segment::QuadTo(x1, y1, x, y) => {
+                    flatten_quad(&mut polyline.points, from, device(x1, y1), device(x, y));
+                }
+                Segment::CubicTo(x1, y1, x2, y2, x, y) => {
+                    flatten_cubic(&mut polyline.points, from, device(x1, y1), device(x2, y2), device(x, y));
+                }
+                Segment::Close => {
+                    start = polyline.points[0];
+                    polyline.points.push(start);
+                    polyline.closed = true;
+                    finish(&mut polylines, &mut polyline);
+                }
+            }
+        }
+
+        finish(&mut polylines, &mut polyline);
+        polylines
+    }
+}
+
+/// A flattened subpath in device coordinates. Closed ones end where they start.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Polyline {
+    pub(crate) points: Vec<Point>,
+    closed: bool,
+}
+
+/// How many lines a curve is flattened into, given how far its control points stray from a straight line.
+fn line_count(deviation: f64) -> usize {
+    ((deviation / TOLERANCE).sqrt().ceil() as usize).clamp(1, MAX_CURVE_LINES)
+}
+
+fn flatten_quad(points: &mut Vec<Point>, p0: Point, p1: Point, p2: Point) {
+    let deviation = (p0.0 - 2.0 * p1.0 + p2.0).hypot(p0.1 - 2.0 * p1.1 + p2.1) / 4.0;
+    let count = line_count(deviation);
+
+    for i in 1..=count {
+        let t = i as f64 / count as f64;
+        let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * (1.0 - t) * t, t * t);
+        points.push((a * p0.0 + b * p1.0 + c * p2.0, a * p0.1 + b * p1.1 + c * p2.1));
+    }
+}
+
+fn flatten_cubic(points: &mut Vec<Point>, p0: Point, p1: Point, p2: Point, p3: Point) {
+    let first = (p0.0 - 2.0 * p1.0 + p2.0).hypot(p0.1 - 2.0 * p1.1 + p2.1);
+    let second = (p1.0 - 2.0 * p2.0 + p3.0).hypot(p1.1 - 2.0 * p2.1 + p3.1);
+    let count = line_count(first.max(second) * 3.0 / 4.0);
+
+    for i in 1..=count {
+        let t = i as f64 / count as f64;
+        let u = 1.0 - t;
+        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        points.push((
+            a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+            a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+        ));
+    }
+}
+
+/// The outline of the stroke along `polylines`, as polygons to be filled with `FillRule::NonZero`.
+/// `width` and the lengths of `dashes` and `dash_offset` are in device pixels.
+pub(crate) fn stroke(
+    polylines: &[Polyline],
+    width: f64,
+    cap: LineCap,
+    join: LineJoin,
+    dashes: &[f64],
+    dash_offset: f64,
+) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    if !(width > 0.0 && width.is_finite()) {
+        return polygons;
+    }
+
+    for polyline in polylines {
+        match dash(&polyline.points, dashes, dash_offset) {
+            Some(pieces) => {
+                for piece in pieces {
+                    stroke_polyline(&mut polygons, &piece, false, width / 2.0, cap, join);
+                }
+            }
+            None => stroke_polyline(&mut polygons, &polyline.points, polyline.closed, width / 2.0, cap, join),
+        }
+    }
+
+    polygons
+}
+
+/// Cuts `points` into the dashes of the pattern, `None` if it does not make any gaps.
+/// An odd number of lengths is repeated, so that dashes and gaps swap on every other round.
+fn dash(points: &[Point], dashes: &[f64], offset: f64) -> Option<Vec<Vec<Point>>> {
+    if dashes.iter().any(|length| !(*length >= 0.0 && length.is_finite())) {
+        return None;
+    }
+
+    let pattern = if dashes.len() % 2 == 1 { dashes.repeat(2) } else { dashes.to_vec() };
+    let total: f64 = pattern.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    // Skip to where in the pattern the path starts
+    let mut offset = if offset.is_finite() { offset.rem_euclid(total) } else { 0.0 };
+    let mut index = 0;
+    while offset >= pattern[index] {
+        offset -= pattern[index];
+        index = (index + 1) % pattern.len();
+    }
+    let mut remaining = pattern[index] - offset;
+
+    let mut pieces = Vec::new();
+    let mut piece = vec![points[0]];
+    for line in points.windows(2) {
+        let (from, to) = (line[0], line[1]);
+        let length = (to.0 - from.0).hypot(to.1 - from.1);
+
+        let mut travelled = 0.0;
+        while length - travelled > remaining {
+            travelled += remaining;
+            let t = travelled / length;
+            let point = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+
+            if index % 2 == 0 {
+                piece.push(point);
+                pieces.push(std::mem::take(&mut piece));
+                if pieces.len() >= MAX_DASHES {
+                    return Some(pieces);
+                }
+            } else {
+                piece = vec![point];
+            }
+
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+
+        remaining -= length - travelled;
+        if index % 2 == 0 {
+            piece.push(to);
+        }
+    }
+
+    if index % 2 == 0 {
+        pieces.push(piece);
+    }
+
+    Some(pieces)
+}
+
+/// Adds the polygons making up the stroke along `points` with half of its width `half`.
+fn stroke_polyline(
+    polygons: &mut Vec<Vec<Point>>,
+    points: &[Point],
+    closed: bool,
+    half: f64,
+    cap: LineCap,
+    join: LineJoin,
+) {
+    // Points on top of each other have no direction between them
+    let mut points = points.to_vec();
+    points.dedup_by(|b, a| (b.0 - a.0).hypot(b.1 - a.1) < 1e-9);
+    if closed && points.len() > 2 && points.first() == points.last() {
+        points.pop();
+    }
+
+    let count = points.len();
+    if count == 1 {
+        // Only the caps of a subpath without length show, facing right
+        add_cap(polygons, points[0], (1.0, 0.0), half, cap);
+        add_cap(polygons, points[0], (-1.0, 0.0), half, cap);
+        return;
+    }
+
+    let lines = if closed { count } else { count - 1 };
+    for i in 0..lines {
+        let (from, to) = (points[i], points[(i + 1) % count]);
+        let (dx, dy) = direction(from, to);
+        let (nx, ny) = (-dy * half, dx * half);
+
+        add_polygon(polygons, vec![
+            (from.0 + nx, from.1 + ny),
+            (to.0 + nx, to.1 + ny),
+            (to.0 - nx, to.1 - ny),
+            (from.0 - nx, from.1 - ny),
+        ]);
+    }
+
+    let corners = if closed { 0..count } else { 1..count - 1 };
+    for i in corners {
+        let (previous, corner, next) = (points[(i + count - 1) % count], points[i], points[(i + 1) % count]);
+        add_join(polygons, previous, corner, next, half, join);
+    }
+
+    if !closed {
+        add_cap(polygons, points[0], direction(points[1], points[0]), half, cap);
+        add_cap(polygons, points[count - 1], direction(points[count - 2], points[count - 1]), half, cap);
+    }
+}
+
+/// Fills the outside of the corner at `corner` between the lines from `previous` and to `next`.
+fn add_join(polygons: &mut Vec<Vec<Point>>, previous: Point, corner: Point, next: Point, half: f64, join: LineJoin) {
+    let (in_x, in_y) = direction(previous, corner);
+    let (out_x, out_y) = direction(corner, next);
+    let cross = in_x * out_y - in_y * out_x;
+    let dot = in_x * out_x + in_y * out_y;
+
+    if cross.abs() < 1e-9 && dot > 0.0 {
+        return;
+    }
+
+    if join == LineJoin::Round {
+        add_polygon(polygons, circle(corner, half));
+        return;
+    }
+
+    // The outside is to the left of a turn to the right, and the other way around
+    let side = if cross > 0.0 { -half } else { half };
+    let (in_normal, out_normal) = ((-in_y, in_x), (-out_y, out_x));
+    let in_edge = (corner.0 + in_normal.0 * side, corner.1 + in_normal.1 * side);
+    let out_edge = (corner.0 + out_normal.0 * side, corner.1 + out_normal.1 * side);
+
+    // The miter sticks out from the corner by half the width over the cosine of half the turn
+    let miter_squared = 2.0 / (1.0 + dot);
+    if join == LineJoin::Miter && miter_squared <= MITER_LIMIT * MITER_LIMIT {
+        let tip = (
+            corner.0 + (in_normal.0 + out_normal.0) * side / (1.0 + dot),
+            corner.1 + (in_normal.1 + out_normal.1) * side / (1.0 + dot),
+        );
+        add_polygon(polygons, vec![corner, in_edge, tip, out_edge]);
+    } else {
+        add_polygon(polygons, vec![corner, in_edge, out_edge]);
+    }
+}
+
+/// Caps the end at `end` of a line running in `direction`.
+fn add_cap(polygons: &mut Vec<Vec<Point>>, end: Point, (dx, dy): Point, half: f64, cap: LineCap) {
+    let (nx, ny) = (-dy * half, dx * half);
+    let (ex, ey) = (dx * half, dy * half);
+
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Round => add_polygon(polygons, circle(end, half)),
+        LineCap::Square => add_polygon(polygons, vec![
+            (end.0 + nx, end.1 + ny),
+            (end.0 + nx + ex, end.1 + ny + ey),
+            (end.0 - nx + ex, end.1 - ny + ey),
+            (end.0 - nx, end.1 - ny),
+        ]),
+    }
+}
+
+/// Adds `polygon` going around the same way as all others, so that overlapping ones do not cancel out.
+fn add_polygon(polygons: &mut Vec<Vec<Point>>, mut polygon: Vec<Point>) {
+    let area: f64 = (0..polygon.len())
+        .map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum();
+
+    if area.is_nan() || area.abs() <= 1e-12 {
+        return;
+    }
+    if area < 0.0 {
+        polygon.reverse();
+    }
+
+    polygons.push(polygon);
+}
+
+fn circle(center: Point, radius: f64) -> Vec<Point> {
+    let step = (1.0 - TOLERANCE / radius).max(-1.0).acos();
+    let count = ((PI / step).ceil() as usize).clamp(8, MAX_CURVE_LINES);
+
+    (0..count)
+        .map(|i| {
+            let (sin, cos) = (2.0 * PI * i as f64 / count as f64).sin_cos();
+            (center.0 + radius * cos, center.1 + radius * sin)
+        })
+        .collect()
+}
+
+/// The unit vector pointing from `from` to `to`, which must be apart.
+fn direction(from: Point, to: Point) -> Point {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = dx.hypot(dy);
+    (dx / length, dy / length)
+}
+
+/// A polygon edge going down from `top` to `bottom`.
+struct Edge {
+    top: f64,
+    bottom: f64,
+    x: f64, // at the top
+    slope: f64,
+    winding: i32,
+}
+
+/// Calls `blend` with the share, from 0 to 1, of each pixel inside `clip` that the polygons cover by `rule`.
+pub(crate) fn rasterize(polygons: &[Vec<Point>], rule: FillRule, clip: Rect, mut blend: impl FnMut(i32, i32, f64)) {
+    let mut edges = Vec::new();
+    let (mut left, mut top, mut right, mut bottom) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+
+    for polygon in polygons {
+        for (i, &from) in polygon.iter().enumerate() {
+            let to = polygon[(i + 1) % polygon.len()];
+            if !(from.0.is_finite() && from.1.is_finite() && to.0.is_finite() && to.1.is_finite()) {
+                continue;
+            }
+
+            left = left.min(from.0);
+            top = top.min(from.1);
+            right = right.max(from.0);
+            bottom = bottom.max(from.1);
+
+            // Horizontal edges are never crossed by a row
+            if from.1 == to.1 {
+                continue;
+            }
+
+            let (upper, lower, winding) = if from.1 < to.1 { (from, to, 1) } else { (to, from, -1) };
+            edges.push(Edge {
+                top: upper.1,
+                bottom: lower.1,
+                x: upper.0,
+                slope: (lower.0 - upper.0) / (lower.1 - upper.1),
+                winding,
+            });
+        }
+    }
+
+    let bounds = pixel_bounds(left, top, right, bottom).intersection(&clip);
+    if edges.is_empty() || bounds.is_empty() {
+        return;
+    }
+
+    edges.sort_by(|a, b| a.top.total_cmp(&b.top));
+
+    let mut coverage = vec![0.0; bounds.width as usize];
+    let mut crossings: Vec<(f64, i32)> = Vec::new();
+
+    for y in bounds.y..bounds.y + bounds.height as i32 {
+        let (row_top, row_bottom) = (y as f64, y as f64 + 1.0);
+        let active: Vec<&Edge> = edges
+            .iter()
+            .take_while(|edge| edge.top < row_bottom)
+            .filter(|edge| edge.bottom > row_top)
+            .collect();
+        if active.is_empty() {
+            continue;
+        }
+
+        coverage.fill(0.0);
+        for sample in 0..SAMPLES {
+            let sample_y = row_top + (sample as f64 + 0.5) / SAMPLES as f64;
+
+            crossings.clear();
+            crossings.extend(
+                active
+                    .iter()
+                    .filter(|edge| edge.top <= sample_y && sample_y < edge.bottom)
+                    .map(|edge| (edge.x + (sample_y - edge.top) * edge.slope, edge.winding)),
+            );
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                let inside = match rule {
+                    FillRule::NonZero => winding != 0,
+                    FillRule::EvenOdd => winding % 2 != 0,
+                };
+
+                if inside {
+                    add_span(&mut coverage, bounds.x as f64, pair[0].0, pair[1].0, 1.0 / SAMPLES as f64);
+                }
+            }
+        }
+
+        for (i, &covered) in coverage.iter().enumerate() {
+            if covered > 0.0 {
+                blend(bounds.x + i as i32, y, covered.min(1.0));
+            }
+        }
+    }
+}
+
+/// Adds `weight` times the share of each pixel between `left` and `right` to a row starting at `origin`.
+fn add_span(row: &mut [f64], origin: f64, left: f64, right: f64, weight: f64) {
+    let width = row.len() as f64;
+    let left = (left - origin).clamp(0.0, width);
+    let right = (right - origin).clamp(0.0, width);
+    if right <= left {
+        return;
+    }
+
+    let first = left.floor() as usize;
+    let last = right.ceil() as usize - 1;
+    if first == last {
+        row[first] += (right - left) * weight;
+        return;
+    }
+
+    row[first] += (first as f64 + 1.0 - left) * weight;
+    for pixel in &mut row[first + 1..last] {
+        *pixel += weight;
+    }
+    row[last] += (right - last as f64) * weight;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    fn coverage(polygons: &[Vec<Point>], rule: FillRule) -> BTreeMap<(i32, i32), f64> {
+        let mut pixels = BTreeMap::new();
+        rasterize(polygons, rule, Rect::new(0, 0, 16, 16), |x, y, coverage| {
+            pixels.insert((x, y), coverage);
+        });
+        pixels
+    }
+
+    fn square(left: f64, top: f64, size: f64) -> Vec<Point> {
+        vec![(left, top), (left + size, top), (left + size, top + size), (left, top + size)]
+    }
+
+    #[test]
+    fn fills_aligned_square_exactly() {
+        let pixels = coverage(&[square(1.0, 1.0, 2.0)], FillRule::NonZero);
+        let expected = BTreeMap::from([((1, 1), 1.0), ((2, 1), 1.0), ((1, 2), 1.0), ((2, 2), 1.0)]);
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn covers_pixels_partially_along_rows() {
+        let pixels = coverage(&[vec![(0.5, 0.0), (1.5, 0.0), (1.5, 1.0), (0.5, 1.0)]], FillRule::NonZero);
+        assert_eq!(pixels, BTreeMap::from([((0, 0), 0.5), ((1, 0), 0.5)]));
+    }
+
+    #[test]
+    fn even_odd_leaves_hole() {
+        let polygons = [square(0.0, 0.0, 4.0), square(1.0, 1.0, 2.0)];
+
+        let even_odd = coverage(&polygons, FillRule::EvenOdd);
+        assert_eq!(even_odd.len(), 12);
+        assert!(!even_odd.contains_key(&(1, 1)) && !even_odd.contains_key(&(2, 2)));
+
+        let non_zero = coverage(&polygons, FillRule::NonZero);
+        assert_eq!(non_zero.len(), 16);
+    }
+
+    #[test]
+    fn dashes_with_offset_and_zero_lengths() {
+        let pieces = dash(&[(0.0, 0.0), (10.0, 0.0)], &[2.0, 1.0, 0.0, 1.0], 1.0).unwrap();
+        let starts_and_ends: Vec<(f64, f64)> = pieces.iter().map(|piece| (piece[0].0, piece[piece.len() - 1].0)).collect();
+
+        assert_eq!(starts_and_ends, vec![(0.0, 1.0), (2.0, 2.0), (3.0, 5.0), (6.0, 6.0), (7.0, 9.0)]);
+    }
+
+    #[test]
+    fn repeats_odd_dash_patterns_and_ignores_gapless_ones() {
+        let pieces = dash(&[(0.0, 0.0), (10.0, 0.0)], &[3.0], 0.0).unwrap();
+        assert_eq!(pieces, vec![vec![(0.0, 0.0), (3.0, 0.0)], vec![(6.0, 0.0), (9.0, 0.0)]]);
+
+        assert!(dash(&[(0.0, 0.0), (10.0, 0.0)], &[], 0.0).is_none());
+        assert!(dash(&[(0.0, 0.0), (10.0, 0.0)], &[0.0, 0.0], 0.0).is_none());
+    }
+
+    #[test]
+    fn miters_right_angles() {
+        let mut polygons = Vec::new();
+        add_join(&mut polygons, (0.0, 0.0), (10.0, 0.0), (10.0, 10.0), 1.0, LineJoin::Miter);
+
+        assert_eq!(polygons.len(), 1);
+        assert!(polygons[0].contains(&(11.0, -1.0)));
+    }
+
+    #[test]
+    fn bevels_miters_past_limit() {
+        // Turning back by 170 degrees makes a miter about 11 times the stroke width
+        let (sin, cos) = 10_f64.to_radians().sin_cos();
+        let mut polygons = Vec::new();
+        add_join(&mut polygons, (0.0, 0.0), (10.0, 0.0), (10.0 - 10.0 * cos, 10.0 * sin), 1.0, LineJoin::Miter);
+
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 3);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::client::Client;
+use crate::config::{self, Config, ConfigError, ModuleRef, Setup};
+use crate::modules::{ErrorIndicatorModule, ModuleHandle};
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+/// Where the bars come from: a config file, or the default config if there is none at startup.
+/// Reloading keeps the current bars if the new config is broken or gone, and flags them instead.
+#[derive(Clone)]
+pub struct ConfigSource {
+    path: Option<PathBuf>,
+    indicator: Rc<RefCell<ErrorIndicatorModule>>,
+    /// Addressable modules of the config in use
+    modules: Rc<RefCell<Vec<ModuleRef>>>,
+}
+
+impl ConfigSource {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            indicator: Rc::default(),
+            modules: Rc::default(),
+        }
+    }
+
+    /// Loads the config file, falling back to the default config if it does not exist.
+    pub fn load(&self) -> Result<Setup, ConfigError> {
+        let config = match self.path.as_deref().filter(|path| path.exists()) {
+            Some(path) => Config::load(path)?,
+            None => Config::parse(config::DEFAULT_CONFIG, None)?,
+        };
+
+        self.setup(config)
+    }
+
+    fn setup(&self, config: Config) -> Result<Setup, ConfigError> {
+        let indicator: ModuleHandle = self.indicator.clone();
+        config.setup(&indicator)
+    }
+
+    /// Replaces the client's bars with those of `setup`.
+    pub fn apply(&self, client: &mut Client, setup: Setup) {
+        client.set_bars(setup.bars);
+        *self.modules.borrow_mut() = setup.modules;
+    }
+
+    /// Loads the config file again and applies it. Unlike `load`, a missing file is an error rather
+    /// than a reason to show the default bars, as it is usually in the middle of being replaced.
+    pub fn reload(&self, client: &mut Client) -> Result<(), ConfigError> {
+        let config = match self.path.as_deref() {
+            Some(path) => Config::load(path),
+            None => Config::parse(config::DEFAULT_CONFIG, None),
+        };
+        let result = config.and_then(|config| self.setup(config)).map(|setup| self.apply(client, setup));
+        self.indicator.borrow_mut().error = result.as_ref().err().map(ConfigError::to_string);
+
+        let indicator: ModuleHandle = self.indicator.clone();
+        client.mark_changed(&[indicator]);
+
+        result
+    }
+
+    /// The modules of the current config named `name`.
+    pub fn modules_named(&self, name: &str) -> Vec<ModuleHandle> {
+        self.find_modules(|module| module.name.as_deref() == Some(name))
+    }
+
+    /// The modules of the current config refreshed by `SIGRTMIN` plus `signal`.
+    pub fn modules_with_signal(&self, signal: u32) -> Vec<ModuleHandle> {
+        self.find_modules(|module| module.signal == Some(signal))
+    }
+
+    fn find_modules(&self, predicate: impl Fn(&ModuleRef) -> bool) -> Vec<ModuleHandle> {
+        self.modules
+            .borrow()
+            .iter()
+            .filter(|module| predicate(module))
+            .map(|module| module.module.clone())
+            .collect()
+    }
+
+    /// Reloads whenever the config file has been written or moved into place. Watches the directory
+    /// rather than the file, since editors often replace files instead of writing them. Files being
+    /// created or removed are ignored, they are still empty or about to be replaced.
+    pub fn watch(&self, client: &mut Client) -> nix::Result<()> {
+        // Without a config directory there is nothing to watch, the default config is all there is
+        let Some((dir, name)) = self.path.as_deref().and_then(split_path).filter(|(dir, _)| dir.is_dir()) else {
+            return Ok(());
+        };
+
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        inotify.add_watch(dir, AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO)?;
+
+        let source = self.clone();
+        client.add_source(inotify, move |client, inotify| {
+            let mut changed = false;
+
+            // Drain everything queued, an editor saving a file causes a burst of events
+            while let Ok(events) = inotify.read_events() {
+                changed |= events.iter().any(|event| event.name.as_ref() == Some(&name));
+            }
+
+            if changed {
+                if let Err(error) = source.reload(client) {
+                    client.report(&error.into());
+                }
+            }
+
+            true
+        });
+
+        Ok(())
+    }
+}
+
+fn split_path(path: &Path) -> Option<(&Path, OsString)> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    Some((dir, path.file_name()?.to_os_string()))
+}
@@ -0,0 +1,58 @@
+use crate::client::Client;
+use crate::reload::ConfigSource;
+
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+
+/// Handles signals from the event loop through a signalfd:
+///
+/// - `SIGTERM` and `SIGINT` stop the bar, taking its surfaces down cleanly
+/// - `SIGUSR1` toggles the visibility of all bars
+/// - `SIGUSR2` reloads the config
+/// - `SIGRTMIN+N` refreshes the modules configured with `signal = N`
+///
+/// The signals are blocked for the calling thread, so this has to run before any other thread is spawned.
+pub fn handle(client: &mut Client, source: ConfigSource) -> nix::Result<()> {
+    let mut mask = SigSet::empty();
+    for signal in [Signal::SIGTERM, Signal::SIGINT, Signal::SIGUSR1, Signal::SIGUSR2] {
+        mask.add(signal);
+    }
+
+    // Real-time signals have no `Signal` variant, so add them to the underlying set
+    let mut raw = *mask.as_ref();
+    for signal in libc::SIGRTMIN()..=libc::SIGRTMAX() {
+        unsafe { libc::sigaddset(&mut raw, signal) };
+    }
+    let mask = unsafe { SigSet::from_sigset_t_unchecked(raw) };
+
+    // Block only once the signalfd exists, failing to create it must not leave SIGTERM blocked and unread
+    let signal_fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?;
+    mask.thread_block()?;
+
+    client.add_source(signal_fd, move |client, signal_fd| {
+        while let Ok(Some(info)) = signal_fd.read_signal() {
+            let signal = info.ssi_signo as i32;
+
+            match Signal::try_from(signal) {
+                Ok(Signal::SIGTERM | Signal::SIGINT) => client.stop(),
+                Ok(Signal::SIGUSR1) => {
+                    client.set_visible(None, None);
+                }
+                Ok(Signal::SIGUSR2) => {
+                    if let Err(error) = source.reload(client) {
+                        client.report(&error.into());
+                    }
+                }
+                _ if signal >= libc::SIGRTMIN() => {
+                    let modules = source.modules_with_signal((signal - libc::SIGRTMIN()) as u32);
+                    client.refresh_modules(&modules);
+                }
+                _ => {}
+            }
+        }
+
+        true
+    });
+
+    Ok(())
+}
@@ -1,18 +1,65 @@
+use crate::buffer::BufferBusy;
+use crate::input::{self, PointerEvent, Seat, TouchEvent};
+use crate::output::Output;
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
 use wayland_client::{
     delegate_noop,
     protocol::*,
+    Proxy,
 };
 
+use wayland_protocols::wp::fractional_scale::v1::client::*;
+use wayland_protocols::wp::viewporter::client::*;
+use wayland_protocols::xdg::xdg_output::zv1::client::*;
 use wayland_protocols_wlr::layer_shell::v1::client::*;
 
+pub(crate) type BarId = u32;
+
+#[derive(Default)]
+pub(crate) struct LayerState {
+    pub(crate) configured: bool,
+    pub(crate) closed: bool,
+    pub(crate) needs_redraw: bool,
+    pub(crate) frame_pending: bool,
+
+    /// Size from the latest configure, zero meaning the bar may choose
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+
+    /// Global names of the outputs the surface is shown on
+    pub(crate) outputs: Vec<u32>,
+    /// Scale suggested by the compositor, in 120ths
+    pub(crate) preferred_scale: Option<u32>,
+}
+
 #[derive(Default)]
 pub struct State {
     pub(crate) running: bool,
-    pub(crate) configured: bool,
 
     pub(crate) compositor: Option<wl_compositor::WlCompositor>,
     pub(crate) layer_shell: Option<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
     pub(crate) shm: Option<wl_shm::WlShm>,
+    pub(crate) xdg_output_manager: Option<zxdg_output_manager_v1::ZxdgOutputManagerV1>,
+    pub(crate) fractional_scale_manager: Option<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>,
+    pub(crate) viewporter: Option<wp_viewporter::WpViewporter>,
+
+    pub(crate) outputs: Vec<Output>,
+    pub(crate) outputs_changed: bool,
+
+    pub(crate) seats: Vec<Seat>,
+    pub(crate) pointer_events: Vec<PointerEvent>,
+    pub(crate) touch_events: Vec<TouchEvent>,
+
+    pub(crate) layers: HashMap<BarId, LayerState>,
+}
+
+impl State {
+    fn output_mut(&mut self, global_name: u32) -> Option<&mut Output> {
+        self.outputs.iter_mut().find(|output| output.global_name == global_name)
+    }
 }
 
 impl wayland_client::Dispatch<wl_registry::WlRegistry, ()> for State {
@@ -24,57 +71,274 @@ impl wayland_client::Dispatch<wl_registry::WlRegistry, ()> for State {
         _: &wayland_client::Connection,
         qh: &wayland_client::QueueHandle<Self>,
     ) {
-        if let wl_registry::Event::Global { name, interface, .. } = event {
-            match interface.as_str() {
-                "wl_compositor" => {
-                    state.compositor = Some(
-                        registry.bind::<wl_compositor::WlCompositor, _, _>(name, 1, qh, ()),
-                    );
+        match event {
+            wl_registry::Event::Global { name, interface, version } => {
+                match interface.as_str() {
+                    "wl_compositor" => {
+                        state.compositor = Some(
+                            registry.bind::<wl_compositor::WlCompositor, _, _>(name, version.min(4), qh, ()),
+                        );
+                    }
+                    "zwlr_layer_shell_v1" => {
+                        state.layer_shell = Some(
+                            registry.bind::<zwlr_layer_shell_v1::ZwlrLayerShellV1, _, _>(name, version.min(4), qh, ()),
+                        );
+                    }
+                    "wl_shm" => {
+                        state.shm = Some(
+                            registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ()),
+                        )
+                    }
+                    "wl_output" => {
+                        let wl_output = registry.bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, name);
+                        let mut output = Output::new(name, wl_output);
+
+                        if let Some(manager) = &state.xdg_output_manager {
+                            output.xdg_output = Some(manager.get_xdg_output(&output.wl_output, qh, name));
+                        }
+
+                        state.outputs.push(output);
+                    }
+                    "wl_seat" => {
+                        let wl_seat = registry.bind::<wl_seat::WlSeat, _, _>(name, version.min(5), qh, name);
+                        state.seats.push(Seat::new(name, wl_seat));
+                    }
+                    "zxdg_output_manager_v1" => {
+                        let manager = registry.bind::<zxdg_output_manager_v1::ZxdgOutputManagerV1, _, _>(name, version.min(3), qh, ());
+
+                        // Outputs announced before the manager still need their xdg-output
+                        for output in &mut state.outputs {
+                            output.xdg_output = Some(manager.get_xdg_output(&output.wl_output, qh, output.global_name));
+                        }
+
+                        state.xdg_output_manager = Some(manager);
+                    }
+                    "wp_fractional_scale_manager_v1" => {
+                        state.fractional_scale_manager = Some(
+                            registry.bind::<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, _, _>(name, 1, qh, ()),
+                        );
+                    }
+                    "wp_viewporter" => {
+                        state.viewporter = Some(
+                            registry.bind::<wp_viewporter::WpViewporter, _, _>(name, 1, qh, ()),
+                        );
+                    }
+                    _ => {
+                        // eprintln!("[{name}]: {interface}");
+                    }
                 }
-                "zwlr_layer_shell_v1" => {
-                    state.layer_shell = Some(
-                        registry.bind::<zwlr_layer_shell_v1::ZwlrLayerShellV1, _, _>(name, 1, qh, ()),
-                    );
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if input::remove_seat(state, name) {
+                    return;
                 }
-                "wl_shm" => {
-                    state.shm = Some(
-                        registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ()),
-                    )
+
+                if let Some(index) = state.outputs.iter().position(|output| output.global_name == name) {
+                    let output = state.outputs.remove(index);
+                    output.destroy();
+                    state.outputs_changed = true;
                 }
-                _ => {
-                    // eprintln!("[{name}]: {interface}");
+            }
+            _ => {}
+        }
+    }
+}
+
+impl wayland_client::Dispatch<wl_output::WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        global_name: &u32,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*global_name) else {
+            return;
+        };
+
+        match event {
+            wl_output::Event::Mode { flags, width, height, .. } => {
+                let current = matches!(
+                    flags.into_result(),
+                    Ok(flags) if flags.contains(wl_output::Mode::Current)
+                );
+
+                if current {
+                    output.mode_size = Some((width as u32, height as u32));
+                }
+            }
+            wl_output::Event::Scale { factor } => {
+                output.scale = factor;
+            }
+            wl_output::Event::Name { name } => {
+                output.name.get_or_insert(name);
+            }
+            wl_output::Event::Description { description } => {
+                output.description.get_or_insert(description);
+            }
+            wl_output::Event::Done => {
+                output.ready = true;
+                state.outputs_changed = true;
+
+                // The scale may have changed, bars work out theirs when drawing
+                for layer in state.layers.values_mut() {
+                    layer.needs_redraw = true;
                 }
             }
+            _ => {}
+        }
+    }
+}
+
+impl wayland_client::Dispatch<zxdg_output_v1::ZxdgOutputV1, u32> for State {
+    fn event(
+        state: &mut Self,
+        xdg_output: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        global_name: &u32,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*global_name) else {
+            return;
+        };
+
+        match event {
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.logical_size = Some((width as u32, height as u32));
+            }
+            zxdg_output_v1::Event::Name { name } => {
+                output.name = Some(name);
+            }
+            zxdg_output_v1::Event::Description { description } => {
+                output.description = Some(description);
+            }
+            // Since version 3 xdg-output changes are applied atomically by wl_output.done
+            zxdg_output_v1::Event::Done if xdg_output.version() < 3 => {
+                output.ready = true;
+                state.outputs_changed = true;
+            }
+            _ => {}
         }
     }
 }
 
-impl wayland_client::Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for State {
+impl wayland_client::Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, BarId> for State {
     fn event(
         state: &mut Self,
         layer_surface: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
         event: zwlr_layer_surface_v1::Event,
-        _: &(),
+        bar_id: &BarId,
         _: &wayland_client::Connection,
         _: &wayland_client::QueueHandle<Self>,
     ) {
+        let Some(layer) = state.layers.get_mut(bar_id) else {
+            return;
+        };
+
         match event {
             zwlr_layer_surface_v1::Event::Closed => {
-                state.running = false;
+                layer.closed = true;
             }
             zwlr_layer_surface_v1::Event::Configure { serial, width, height, .. } => {
-                eprintln!("{width}x{height}");
                 layer_surface.ack_configure(serial);
-                state.configured = true;
+                layer.configured = true;
+                layer.width = width;
+                layer.height = height;
+                layer.needs_redraw = true;
             }
             _ => {}
         }
     }
 }
 
+impl wayland_client::Dispatch<wl_surface::WlSurface, BarId> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_surface::WlSurface,
+        event: wl_surface::Event,
+        bar_id: &BarId,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        let Some(layer) = state.layers.get_mut(bar_id) else {
+            return;
+        };
+
+        match event {
+            wl_surface::Event::Enter { output } => {
+                if let Some(&global_name) = output.data::<u32>() {
+                    layer.outputs.push(global_name);
+                    layer.needs_redraw = true;
+                }
+            }
+            wl_surface::Event::Leave { output } => {
+                if let Some(global_name) = output.data::<u32>() {
+                    layer.outputs.retain(|other| other != global_name);
+                    layer.needs_redraw = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl wayland_client::Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, BarId> for State {
+    fn event(
+        state: &mut Self,
+        _: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        bar_id: &BarId,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            if let Some(layer) = state.layers.get_mut(bar_id) {
+                layer.preferred_scale = Some(scale);
+                layer.needs_redraw = true;
+            }
+        }
+    }
+}
+
+impl wayland_client::Dispatch<wl_callback::WlCallback, BarId> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        bar_id: &BarId,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            if let Some(layer) = state.layers.get_mut(bar_id) {
+                layer.frame_pending = false;
+            }
+        }
+    }
+}
+
+impl wayland_client::Dispatch<wl_buffer::WlBuffer, BufferBusy> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        busy: &BufferBusy,
+        _: &wayland_client::Connection,
+        _: &wayland_client::QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            busy.store(false, Ordering::Release);
+        }
+    }
+}
+
 delegate_noop!(State: ignore wl_compositor::WlCompositor);
-delegate_noop!(State: ignore wl_surface::WlSurface);
 delegate_noop!(State: ignore zwlr_layer_shell_v1::ZwlrLayerShellV1);
+delegate_noop!(State: ignore zxdg_output_manager_v1::ZxdgOutputManagerV1);
+delegate_noop!(State: ignore wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1);
+delegate_noop!(State: ignore wp_viewporter::WpViewporter);
+delegate_noop!(State: ignore wp_viewport::WpViewport);
 delegate_noop!(State: ignore wl_shm::WlShm);
 delegate_noop!(State: ignore wl_shm_pool::WlShmPool);
-delegate_noop!(State: ignore wl_buffer::WlBuffer);